@@ -0,0 +1,270 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, prelude::*, BufWriter},
+    path::Path,
+};
+
+use log::warn;
+
+use super::{BitField, Device, Peripheral, Register};
+
+/// Writes `<periph>_reg.h` with the register and field defines of every
+/// peripheral, in the same format as the headers of the esp-idf, and
+/// optionally `<periph>_struct.h` with a bitfield union per register
+pub fn build_c_headers(device: &Device, dir: &Path, structs: bool) -> io::Result<()> {
+    let mut overlapping = vec![];
+
+    fs::create_dir_all(dir)?;
+    for (name, p) in &device.peripherals {
+        let mut registers: Vec<&Register> = p.registers.iter().collect();
+        registers.sort_by_key(|r| r.address);
+
+        let filename = dir.join(format!("{}_reg.h", name.to_lowercase()));
+        let mut out = BufWriter::new(File::create(filename)?);
+        write_reg_header(name, p, &registers, &mut out)?;
+
+        if structs {
+            let filename = dir.join(format!("{}_struct.h", name.to_lowercase()));
+            let mut out = BufWriter::new(File::create(filename)?);
+            write_struct_header(name, &registers, &mut out, &mut overlapping)?;
+        }
+    }
+
+    if overlapping.len() > 0 {
+        warn!(
+            target: "build",
+            "The following overlap and were left out of the structs {:?}",
+            overlapping
+        );
+    }
+
+    Ok(())
+}
+
+fn write_reg_header<W: Write>(
+    name: &str,
+    p: &Peripheral,
+    registers: &[&Register],
+    out: &mut W,
+) -> io::Result<()> {
+    let guard = format!("_SOC_{}_REG_H_", name.to_uppercase());
+    let base = format!("DR_REG_{}_BASE", name.to_uppercase());
+
+    writeln!(out, "/* Generated by header2svd, do not edit */")?;
+    writeln!(out, "#ifndef {}", guard)?;
+    writeln!(out, "#define {}", guard)?;
+    writeln!(out)?;
+    writeln!(out, "#ifndef BIT")?;
+    writeln!(out, "#define BIT(nr) (1UL << (nr))")?;
+    writeln!(out, "#endif")?;
+    writeln!(out)?;
+    writeln!(out, "#define {} 0x{:08x}", base, p.address)?;
+
+    // field names are not guaranteed to be unique within a peripheral,
+    // duplicates are prefixed with the name of their register
+    let mut field_names = HashMap::new();
+    for field in registers.iter().flat_map(|r| &r.bit_fields) {
+        *field_names.entry(field.name.to_uppercase()).or_insert(0) += 1;
+    }
+
+    for r in registers {
+        writeln!(out)?;
+        writeln!(out, "#define {}_REG ({} + 0x{:x})", r.name, base, r.address)?;
+
+        for field in &r.bit_fields {
+            let mut define = field.name.to_uppercase();
+            if field_names[&define] > 1 {
+                define = format!("{}_{}", r.name, define);
+            }
+            write_field_defines(&define, field, out)?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "#endif /* {} */", guard)
+}
+
+fn write_field_defines<W: Write>(define: &str, field: &BitField, out: &mut W) -> io::Result<()> {
+    let offset = field.bits.offset();
+    let width = field.bits.width();
+    let value_mask = (1u64 << width) - 1;
+
+    let (bits, default) = if width == 1 {
+        (
+            offset.to_string(),
+            format!("1'b{:b}", field.reset_value & 1),
+        )
+    } else {
+        (
+            format!("{}:{}", offset + width - 1, offset),
+            format!("{}'h{:x}", width, field.reset_value),
+        )
+    };
+
+    writeln!(
+        out,
+        "/* {} : {} ;bitpos:[{}] ;default: {} ; */",
+        define,
+        field.type_.short_name(),
+        bits,
+        default
+    )?;
+    if !field.description.trim().is_empty() {
+        writeln!(out, "/*description: {}*/", c_comment(&field.description))?;
+    }
+
+    if width == 1 {
+        writeln!(out, "#define {}  (BIT({}))", define, offset)?;
+        writeln!(out, "#define {}_M  (BIT({}))", define, offset)?;
+    } else {
+        writeln!(out, "#define {}  0x{:08X}", define, value_mask << offset)?;
+        writeln!(out, "#define {0}_M  (({0}_V)<<({0}_S))", define)?;
+    }
+    writeln!(out, "#define {}_V  0x{:X}", define, value_mask)?;
+    writeln!(out, "#define {}_S  {}", define, offset)
+}
+
+fn write_struct_header<W: Write>(
+    name: &str,
+    registers: &[&Register],
+    out: &mut W,
+    overlapping: &mut Vec<String>,
+) -> io::Result<()> {
+    let guard = format!("_SOC_{}_STRUCT_H_", name.to_uppercase());
+
+    writeln!(out, "/* Generated by header2svd, do not edit */")?;
+    writeln!(out, "#ifndef {}", guard)?;
+    writeln!(out, "#define {}", guard)?;
+    writeln!(out)?;
+    writeln!(out, "#include <stdint.h>")?;
+    writeln!(out)?;
+    writeln!(out, "typedef volatile struct {{")?;
+
+    let mut members = vec![];
+    let mut offset = 0;
+    for r in registers {
+        if r.address < offset {
+            overlapping.push(format!("{}::{}", name, r.name));
+            continue;
+        }
+
+        let gap = r.address - offset;
+        if gap % 4 == 0 && gap > 0 {
+            writeln!(out, "    uint32_t reserved_{:x}[{}];", offset, gap / 4)?;
+        } else if gap > 0 {
+            writeln!(out, "    uint8_t reserved_{:x}[{}];", offset, gap)?;
+        }
+        offset = r.address + u32::from(r.size()) / 8;
+        let ty = format!("uint{}_t", r.size());
+
+        let prefix = name_prefix(name, &r.name);
+        let mut member = c_ident(&r.name, prefix);
+        if members.contains(&member) {
+            member = format!("{}_{:x}", member, r.address);
+        }
+
+        if r.bit_fields.is_empty() {
+            writeln!(out, "    {} {};", ty, member)?;
+        } else {
+            writeln!(out, "    union {{")?;
+            writeln!(out, "        struct {{")?;
+            write_bit_fields(name, r, prefix, out, overlapping)?;
+            writeln!(out, "        }};")?;
+            writeln!(out, "        {} val;", ty)?;
+            writeln!(out, "    }} {};", member)?;
+        }
+        members.push(member);
+    }
+
+    writeln!(out, "}} {}_dev_t;", name.to_lowercase())?;
+    writeln!(out)?;
+    writeln!(out, "extern {}_dev_t {};", name.to_lowercase(), name)?;
+    writeln!(out)?;
+    writeln!(out, "#endif /* {} */", guard)
+}
+
+fn write_bit_fields<W: Write>(
+    name: &str,
+    r: &Register,
+    prefix: &str,
+    out: &mut W,
+    overlapping: &mut Vec<String>,
+) -> io::Result<()> {
+    let mut fields: Vec<&BitField> = r.bit_fields.iter().collect();
+    fields.sort_by_key(|f| f.bits.offset());
+    let ty = format!("uint{}_t", r.size());
+
+    let mut members = vec![];
+    let mut bit = 0;
+    for field in fields {
+        // fields past the end of the register overlap the next one
+        let offset = field.bits.offset();
+        if offset < bit || offset + field.bits.width() > r.size() {
+            overlapping.push(format!("{}::{}::{}", name, r.name, field.name));
+            continue;
+        }
+        if offset > bit {
+            writeln!(out, "            {} reserved{}: {};", ty, bit, offset - bit)?;
+        }
+        bit = offset + field.bits.width();
+
+        let mut member = c_ident(&field.name.to_uppercase(), prefix);
+        if members.contains(&member) {
+            member = format!("{}_{}", member, offset);
+        }
+        writeln!(
+            out,
+            "            {} {}: {};",
+            ty,
+            member,
+            field.bits.width()
+        )?;
+        members.push(member);
+    }
+
+    if bit < r.size() {
+        writeln!(
+            out,
+            "            {} reserved{}: {};",
+            ty,
+            bit,
+            r.size() - bit
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The part of a register name shared by all registers of a peripheral,
+/// which is left out of the struct members
+fn name_prefix<'a>(peripheral: &str, reg_name: &'a str) -> &'a str {
+    let peripheral = peripheral.to_uppercase();
+    if reg_name.starts_with(&peripheral) && reg_name[peripheral.len()..].starts_with('_') {
+        &reg_name[..=peripheral.len()]
+    } else {
+        reg_name.find('_').map_or("", |i| &reg_name[..=i])
+    }
+}
+
+fn c_ident(name: &str, prefix: &str) -> String {
+    let ident = name
+        .strip_prefix(prefix)
+        .filter(|rest| !rest.is_empty())
+        .unwrap_or(name)
+        .to_lowercase()
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", ident)
+    } else {
+        ident
+    }
+}
+
+fn c_comment(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("*/", "* /")
+}
@@ -0,0 +1,96 @@
+use std::{fs, path::PathBuf};
+
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Version of what is cached, bump it along with changes to the parsers or
+/// the model which give different results for the same headers
+const CACHE_VERSION: u32 = 1;
+
+// 64 bit FNV-1a, unlike the hasher of the standard library its results
+// don't change between releases of Rust
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+fn fnv(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Results kept between runs in a local directory, so unchanged inputs don't
+/// have to be parsed again. Entries are keyed by a hash of everything they
+/// were derived from, see [`Cache::key`].
+pub struct Cache {
+    dir: Option<PathBuf>,
+}
+
+impl Cache {
+    /// A cache in `dir`, or one which keeps nothing when no directory is given
+    pub fn new(dir: Option<&str>) -> Self {
+        Cache {
+            dir: dir.map(PathBuf::from),
+        }
+    }
+
+    /// Hashes the inputs of an entry along with the versions of the cache
+    /// and the tool, as the parsers may give different results for the same
+    /// input after an update
+    pub fn key(inputs: &[&str]) -> String {
+        let version = format!("{}-{}", CACHE_VERSION, env!("CARGO_PKG_VERSION"));
+        let hash = [version.as_str()]
+            .iter()
+            .chain(inputs)
+            .fold(FNV_OFFSET, |hash, input| {
+                // the length separates the inputs, so they can't run into
+                // each other
+                let hash = fnv(hash, &(input.len() as u64).to_le_bytes());
+                fnv(hash, input.as_bytes())
+            });
+        format!("{:016x}", hash)
+    }
+
+    fn path(&self, kind: &str, key: &str) -> Option<PathBuf> {
+        Some(self.dir.as_ref()?.join(format!("{}-{}.json", kind, key)))
+    }
+
+    /// The entry stored for `key`, entries which can't be read are treated as
+    /// missing
+    pub fn get<T: DeserializeOwned>(&self, kind: &str, key: &str) -> Option<T> {
+        let data = fs::read_to_string(self.path(kind, key)?).ok()?;
+        serde_json::from_str(&data).ok()
+    }
+
+    pub fn put<T: Serialize>(&self, kind: &str, key: &str, value: &T) {
+        let path = match self.path(kind, key) {
+            Some(path) => path,
+            None => return,
+        };
+
+        // failing to write the cache only costs time on the next run
+        let written = fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| fs::write(&path, serde_json::to_string(value).unwrap()));
+        if let Err(e) = written {
+            warn!(target: "model", "Failed to write {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv_matches_reference_values() {
+        assert_eq!(fnv(FNV_OFFSET, b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv(FNV_OFFSET, b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv(FNV_OFFSET, b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn keys_separate_their_inputs() {
+        assert_eq!(Cache::key(&["ab", "c"]), Cache::key(&["ab", "c"]));
+        assert_ne!(Cache::key(&["ab", "c"]), Cache::key(&["a", "bc"]));
+        assert_ne!(Cache::key(&["abc"]), Cache::key(&["abc", ""]));
+    }
+}
@@ -0,0 +1,159 @@
+use regex::Regex;
+
+use super::{BitField, Device, Type, WriteConstraint};
+
+// A range of valid values given in a description, `range: 0~255`
+const RANGE: &'static str =
+    r"(?i)\brange\b[^0-9\n]{0,12}(0x[0-9a-f]+|[0-9]+)\s*(?:~|-|to|\.\.|,)\s*(0x[0-9a-f]+|[0-9]+)";
+// Values given a meaning in a description, the `1` of `1: enable` or the
+// `2 or 3` of `2 or 3: divided by 256`
+const ENUMERATED: &'static str = r"(?:^|[\s,;(])((?:[0-9]+\s*(?:or|/)\s*)*[0-9]+)\s*:";
+// Descriptions of the individual bits of a field, `bit[7]: timer enable`
+const BIT_DESCRIPTION: &'static str = r"(?i)\bbits?\s*\[?[0-9]";
+
+fn parse_value(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+struct Finder {
+    re_range: Regex,
+    re_enumerated: Regex,
+    re_bit_description: Regex,
+}
+
+impl Finder {
+    fn new() -> Self {
+        Finder {
+            re_range: Regex::new(RANGE).unwrap(),
+            re_enumerated: Regex::new(ENUMERATED).unwrap(),
+            re_bit_description: Regex::new(BIT_DESCRIPTION).unwrap(),
+        }
+    }
+
+    /// The values which may be written to the field according to its
+    /// description. All values are allowed when the description gives the
+    /// meaning of each of them, when only some are described only those may
+    /// be written.
+    fn write_constraint(&self, field: &BitField) -> Option<WriteConstraint> {
+        let width = field.bits.width();
+        if width <= 1 || width > 32 || field.type_ == Type::ReadOnly {
+            return None;
+        }
+        let max = (1u64 << width) - 1;
+        let description = &field.description;
+
+        if let Some(m) = self.re_range.captures(description) {
+            let (min, range_max) = (parse_value(&m[1])?, parse_value(&m[2])?);
+            if min > range_max || range_max > max {
+                return None;
+            }
+            return Some(WriteConstraint::Range {
+                min,
+                max: range_max,
+            });
+        }
+
+        if self.re_bit_description.is_match(description) {
+            return None;
+        }
+
+        let mut values: Vec<u64> = self
+            .re_enumerated
+            .captures_iter(description)
+            .flat_map(|m| {
+                m[1].split(|c: char| !c.is_ascii_digit())
+                    .filter_map(|v| v.parse().ok())
+                    .collect::<Vec<u64>>()
+            })
+            .collect();
+        values.sort();
+        values.dedup();
+        if values.len() < 2 || values.iter().any(|v| *v > max) {
+            None
+        } else if values.len() as u64 == max + 1 {
+            Some(WriteConstraint::Range { min: 0, max })
+        } else {
+            Some(WriteConstraint::EnumeratedOnly)
+        }
+    }
+}
+
+/// Derives the write constraints of the fields from their descriptions,
+/// constraints which are already set are kept
+pub fn add_write_constraints(device: &mut Device) {
+    let finder = Finder::new();
+
+    for p in device.peripherals.values_mut() {
+        for r in p.registers.iter_mut() {
+            for field in r.bit_fields.iter_mut() {
+                if field.write_constraint.is_none() {
+                    field.write_constraint = finder.write_constraint(field);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::common::Bits;
+
+    fn field(width: u8, description: String) -> BitField {
+        BitField {
+            bits: Bits::Range(0..=width - 1),
+            type_: Type::ReadWrite,
+            description,
+            ..Default::default()
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn range_round_trips(width in 2u8..=32, a in any::<u32>(), b in any::<u32>(), sep in "(~|-| to |\\.\\.)") {
+            let max = (1u64 << width) - 1;
+            let (min, range_max) = (a as u64 & max, b as u64 & max);
+            prop_assume!(min <= range_max);
+            let description = format!("the divider, valid range: {}{}{}", min, sep, range_max);
+
+            prop_assert_eq!(
+                Finder::new().write_constraint(&field(width, description)),
+                Some(WriteConstraint::Range { min, max: range_max })
+            );
+        }
+
+        #[test]
+        fn enumerated_values(width in 2u8..=4, values in prop::collection::btree_set(0u64..16, 2..16)) {
+            let max = (1u64 << width) - 1;
+            prop_assume!(values.iter().all(|v| *v <= max));
+            let description: Vec<String> = values.iter().map(|v| format!("{}: mode {}", v, v)).collect();
+            let expected = if values.len() as u64 == max + 1 {
+                WriteConstraint::Range { min: 0, max }
+            } else {
+                WriteConstraint::EnumeratedOnly
+            };
+
+            prop_assert_eq!(
+                Finder::new().write_constraint(&field(width, description.join(", "))),
+                Some(expected)
+            );
+        }
+
+        #[test]
+        fn bit_descriptions_are_not_values(width in 2u8..=8, bit in 0u8..8) {
+            let description = format!("bit[{}]: timer enable, bit {}: reload", bit, bit);
+
+            prop_assert_eq!(Finder::new().write_constraint(&field(width, description)), None);
+        }
+
+        #[test]
+        fn finding_never_panics(width in 1u8..=32, description in "\\PC*") {
+            Finder::new().write_constraint(&field(width, description));
+        }
+    }
+}
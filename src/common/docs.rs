@@ -0,0 +1,507 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, prelude::*, BufWriter},
+    path::Path,
+};
+
+use super::{BitField, Device, Interrupt, Peripheral, Register};
+
+const STYLE: &'static str = "body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #999; padding: 0.2em 0.5em; }
+td.bits { text-align: center; font-family: monospace; font-size: 0.8em; }
+td.reserved { background: #eee; color: #999; }
+code { font-family: monospace; }";
+
+/// A contiguous part of a register, either a field or reserved bits
+struct Segment<'a> {
+    high: u8,
+    low: u8,
+    field: Option<&'a BitField>,
+}
+
+/// Peripherals are ordered by name, registers by offset
+fn sorted_peripherals(device: &Device) -> Vec<(&String, &Peripheral)> {
+    let mut peripherals: Vec<_> = device.peripherals.iter().collect();
+    peripherals.sort_by(|a, b| a.0.cmp(b.0));
+    peripherals
+}
+
+fn sorted_registers(p: &Peripheral) -> Vec<&Register> {
+    let mut registers: Vec<&Register> = p.registers.iter().collect();
+    registers.sort_by_key(|r| r.address);
+    registers
+}
+
+/// Splits a register into its fields and the reserved bits in between, from
+/// the highest bit down, overlapping fields are left out
+fn segments(r: &Register) -> Vec<Segment<'_>> {
+    let mut fields: Vec<&BitField> = r.bit_fields.iter().collect();
+    fields.sort_by_key(|f| f.bits.offset());
+
+    let mut segments = vec![];
+    let mut bit = 0;
+    for field in fields {
+        let offset = field.bits.offset();
+        if offset < bit {
+            continue;
+        }
+        if offset > bit {
+            segments.push(Segment {
+                high: offset - 1,
+                low: bit,
+                field: None,
+            });
+        }
+        bit = offset + field.bits.width();
+        segments.push(Segment {
+            high: bit - 1,
+            low: offset,
+            field: Some(field),
+        });
+    }
+    if bit < r.size() {
+        segments.push(Segment {
+            high: r.size() - 1,
+            low: bit,
+            field: None,
+        });
+    }
+
+    segments.reverse();
+    segments
+}
+
+/// Peripherals with the same registers as a peripheral earlier in the
+/// alphabet are documented as derived from that one
+fn derived_peripherals(device: &Device) -> HashMap<&str, &str> {
+    let layout = |p: &Peripheral| -> Vec<(String, u32, Vec<(String, u8, u8)>)> {
+        sorted_registers(p)
+            .into_iter()
+            .map(|r| {
+                let fields = r
+                    .bit_fields
+                    .iter()
+                    .map(|f| (f.name.clone(), f.bits.offset(), f.bits.width()))
+                    .collect();
+                (r.name.clone(), r.address, fields)
+            })
+            .collect()
+    };
+
+    let mut derived = HashMap::new();
+    let mut seen: Vec<(&str, _)> = vec![];
+    for (name, p) in sorted_peripherals(device) {
+        let p_layout = layout(p);
+        if p_layout.is_empty() {
+            continue;
+        }
+        match seen.iter().find(|(_, l)| *l == p_layout) {
+            Some((base, _)) => {
+                derived.insert(name.as_str(), *base);
+            }
+            None => seen.push((name.as_str(), p_layout)),
+        }
+    }
+
+    derived
+}
+
+/// Interrupts are matched to peripherals by their name
+fn peripheral_interrupts<'a>(device: &'a Device, name: &str) -> Vec<&'a Interrupt> {
+    let prefix = format!("{}_", name);
+    let mut interrupts: Vec<&Interrupt> = device
+        .interrupts
+        .iter()
+        .filter(|i| i.name == name || i.name.starts_with(&prefix))
+        .collect();
+    interrupts.sort_by_key(|i| i.value);
+    interrupts
+}
+
+fn interrupt_peripheral<'a>(device: &'a Device, interrupt: &Interrupt) -> Option<&'a str> {
+    sorted_peripherals(device)
+        .into_iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| interrupt.name == *name || interrupt.name.starts_with(&format!("{}_", name)))
+        .max_by_key(|name| name.len())
+}
+
+fn bits_string(segment: &Segment) -> String {
+    if segment.high == segment.low {
+        segment.low.to_string()
+    } else {
+        format!("{}:{}", segment.high, segment.low)
+    }
+}
+
+/// Writes an `index.html` listing the peripherals and interrupts, and a page
+/// per peripheral with the diagram and fields of every register
+pub fn build_html(device: &Device, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let derived = derived_peripherals(device);
+    let chip = device.chip.to_string();
+
+    let mut out = BufWriter::new(File::create(dir.join("index.html"))?);
+    html_header(&mut out, &chip)?;
+    writeln!(out, "<h1>{}</h1>", chip)?;
+    writeln!(out, "<h2>Peripherals</h2>")?;
+    writeln!(out, "<table>")?;
+    writeln!(
+        out,
+        "<tr><th>Name</th><th>Address</th><th>Registers</th><th>Derived from</th></tr>"
+    )?;
+    for (name, p) in sorted_peripherals(device) {
+        writeln!(
+            out,
+            "<tr><td><a href=\"{0}.html\">{0}</a></td><td><code>0x{1:08x}</code></td><td>{2}</td><td>{3}</td></tr>",
+            name,
+            p.address,
+            p.registers.len(),
+            derived
+                .get(name.as_str())
+                .map_or(String::new(), |base| format!("<a href=\"{0}.html\">{0}</a>", base))
+        )?;
+    }
+    writeln!(out, "</table>")?;
+
+    if !device.interrupts.is_empty() {
+        let mut interrupts: Vec<&Interrupt> = device.interrupts.iter().collect();
+        interrupts.sort_by_key(|i| i.value);
+
+        writeln!(out, "<h2>Interrupts</h2>")?;
+        writeln!(out, "<table>")?;
+        writeln!(
+            out,
+            "<tr><th>Value</th><th>Name</th><th>Peripheral</th><th>Description</th></tr>"
+        )?;
+        for interrupt in interrupts {
+            writeln!(
+                out,
+                "<tr><td>{}</td><td id=\"{}\">{}</td><td>{}</td><td>{}</td></tr>",
+                interrupt.value,
+                interrupt.name,
+                interrupt.name,
+                interrupt_peripheral(device, interrupt).map_or(String::new(), |p| format!(
+                    "<a href=\"{0}.html\">{0}</a>",
+                    p
+                )),
+                escape_html(interrupt.description.as_deref().unwrap_or(""))
+            )?;
+        }
+        writeln!(out, "</table>")?;
+    }
+    writeln!(out, "</body>\n</html>")?;
+
+    for (name, p) in sorted_peripherals(device) {
+        let mut out = BufWriter::new(File::create(dir.join(format!("{}.html", name)))?);
+        html_peripheral(device, name, p, derived.get(name.as_str()), &mut out)?;
+    }
+
+    Ok(())
+}
+
+fn html_header<W: Write>(out: &mut W, title: &str) -> io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>\n<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>{}</title>", title)?;
+    writeln!(out, "<style>\n{}\n</style>", STYLE)?;
+    writeln!(out, "</head>\n<body>")
+}
+
+fn html_peripheral<W: Write>(
+    device: &Device,
+    name: &str,
+    p: &Peripheral,
+    derived_from: Option<&&str>,
+    out: &mut W,
+) -> io::Result<()> {
+    html_header(out, name)?;
+    writeln!(
+        out,
+        "<p><a href=\"index.html\">{}</a></p>",
+        device.chip.to_string()
+    )?;
+    writeln!(out, "<h1>{}</h1>", name)?;
+    if p.description != name {
+        writeln!(out, "<p>{}</p>", escape_html(&p.description))?;
+    }
+    writeln!(out, "<p>Base address <code>0x{:08x}</code></p>", p.address)?;
+    if let Some(base) = derived_from {
+        writeln!(
+            out,
+            "<p>Same registers as <a href=\"{0}.html\">{0}</a></p>",
+            base
+        )?;
+    }
+
+    let interrupts = peripheral_interrupts(device, name);
+    if !interrupts.is_empty() {
+        let links: Vec<String> = interrupts
+            .iter()
+            .map(|i| format!("<a href=\"index.html#{0}\">{0}</a> ({1})", i.name, i.value))
+            .collect();
+        writeln!(out, "<p>Interrupts: {}</p>", links.join(", "))?;
+    }
+
+    let registers = sorted_registers(p);
+    writeln!(out, "<h2>Registers</h2>")?;
+    writeln!(out, "<table>")?;
+    writeln!(
+        out,
+        "<tr><th>Name</th><th>Offset</th><th>Address</th><th>Reset</th><th>Description</th></tr>"
+    )?;
+    for r in &registers {
+        writeln!(
+            out,
+            "<tr><td><a href=\"#{0}\">{0}</a></td><td><code>0x{1:x}</code></td><td><code>0x{2:08x}</code></td><td><code>0x{3:08x}</code></td><td>{4}</td></tr>",
+            r.name,
+            r.address,
+            p.address + r.address,
+            r.reset_value,
+            escape_html(&r.description)
+        )?;
+    }
+    writeln!(out, "</table>")?;
+
+    for r in &registers {
+        writeln!(out, "<h3 id=\"{0}\">{0}</h3>", r.name)?;
+        writeln!(
+            out,
+            "<p>Offset <code>0x{:x}</code>, address <code>0x{:08x}</code>, reset <code>0x{:08x}</code></p>",
+            r.address,
+            p.address + r.address,
+            r.reset_value
+        )?;
+        if r.description != r.name {
+            writeln!(out, "<p>{}</p>", escape_html(&r.description))?;
+        }
+        if r.bit_fields.is_empty() {
+            continue;
+        }
+
+        let segments = segments(r);
+
+        // one column per bit, fields span the columns of their bits
+        writeln!(out, "<table>")?;
+        write!(out, "<tr>")?;
+        for bit in (0..r.size()).rev() {
+            write!(out, "<td class=\"bits\">{}</td>", bit)?;
+        }
+        writeln!(out, "</tr>")?;
+        write!(out, "<tr>")?;
+        for segment in &segments {
+            let span = segment.high - segment.low + 1;
+            match segment.field {
+                Some(field) => write!(
+                    out,
+                    "<td class=\"bits\" colspan=\"{}\"><a href=\"#{}.{}\">{}</a></td>",
+                    span, r.name, field.name, field.name
+                )?,
+                None => write!(
+                    out,
+                    "<td class=\"bits reserved\" colspan=\"{}\">reserved</td>",
+                    span
+                )?,
+            }
+        }
+        writeln!(out, "</tr>")?;
+        writeln!(out, "</table>")?;
+
+        writeln!(out, "<table>")?;
+        writeln!(
+            out,
+            "<tr><th>Bits</th><th>Name</th><th>Access</th><th>Reset</th><th>Description</th></tr>"
+        )?;
+        for segment in &segments {
+            if let Some(field) = segment.field {
+                writeln!(
+                    out,
+                    "<tr id=\"{}.{}\"><td>{}</td><td>{}</td><td>{}</td><td><code>0x{:x}</code></td><td>{}</td></tr>",
+                    r.name,
+                    field.name,
+                    bits_string(segment),
+                    field.name,
+                    field.type_.short_name(),
+                    field.reset_value,
+                    escape_html(&field.description)
+                )?;
+            }
+        }
+        writeln!(out, "</table>")?;
+    }
+
+    writeln!(out, "</body>\n</html>")
+}
+
+/// Writes a `README.md` listing the peripherals and interrupts, and a file per
+/// peripheral with the layout and fields of every register
+pub fn build_markdown(device: &Device, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let derived = derived_peripherals(device);
+
+    let mut out = BufWriter::new(File::create(dir.join("README.md"))?);
+    writeln!(out, "# {}", device.chip.to_string())?;
+    writeln!(out)?;
+    writeln!(out, "## Peripherals")?;
+    writeln!(out)?;
+    writeln!(out, "| Name | Address | Registers | Derived from |")?;
+    writeln!(out, "| --- | --- | --- | --- |")?;
+    for (name, p) in sorted_peripherals(device) {
+        writeln!(
+            out,
+            "| [{0}]({0}.md) | `0x{1:08x}` | {2} | {3} |",
+            name,
+            p.address,
+            p.registers.len(),
+            derived
+                .get(name.as_str())
+                .map_or(String::new(), |base| format!("[{0}]({0}.md)", base))
+        )?;
+    }
+
+    if !device.interrupts.is_empty() {
+        let mut interrupts: Vec<&Interrupt> = device.interrupts.iter().collect();
+        interrupts.sort_by_key(|i| i.value);
+
+        writeln!(out)?;
+        writeln!(out, "## Interrupts")?;
+        writeln!(out)?;
+        writeln!(out, "| Value | Name | Peripheral | Description |")?;
+        writeln!(out, "| --- | --- | --- | --- |")?;
+        for interrupt in interrupts {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} |",
+                interrupt.value,
+                interrupt.name,
+                interrupt_peripheral(device, interrupt)
+                    .map_or(String::new(), |p| format!("[{0}]({0}.md)", p)),
+                escape_markdown(interrupt.description.as_deref().unwrap_or(""))
+            )?;
+        }
+    }
+
+    for (name, p) in sorted_peripherals(device) {
+        let mut out = BufWriter::new(File::create(dir.join(format!("{}.md", name)))?);
+        markdown_peripheral(device, name, p, derived.get(name.as_str()), &mut out)?;
+    }
+
+    Ok(())
+}
+
+fn markdown_peripheral<W: Write>(
+    device: &Device,
+    name: &str,
+    p: &Peripheral,
+    derived_from: Option<&&str>,
+    out: &mut W,
+) -> io::Result<()> {
+    writeln!(out, "# {}", name)?;
+    writeln!(out)?;
+    if p.description != name {
+        writeln!(out, "{}", p.description)?;
+        writeln!(out)?;
+    }
+    writeln!(out, "Base address `0x{:08x}`", p.address)?;
+    if let Some(base) = derived_from {
+        writeln!(out)?;
+        writeln!(out, "Same registers as [{0}]({0}.md)", base)?;
+    }
+
+    let interrupts = peripheral_interrupts(device, name);
+    if !interrupts.is_empty() {
+        let names: Vec<String> = interrupts
+            .iter()
+            .map(|i| format!("{} ({})", i.name, i.value))
+            .collect();
+        writeln!(out)?;
+        writeln!(out, "Interrupts: {}", names.join(", "))?;
+    }
+
+    let registers = sorted_registers(p);
+    writeln!(out)?;
+    writeln!(out, "## Registers")?;
+    writeln!(out)?;
+    writeln!(out, "| Name | Offset | Address | Reset | Description |")?;
+    writeln!(out, "| --- | --- | --- | --- | --- |")?;
+    for r in &registers {
+        writeln!(
+            out,
+            "| [{}](#{}) | `0x{:x}` | `0x{:08x}` | `0x{:08x}` | {} |",
+            r.name,
+            r.name.to_lowercase(),
+            r.address,
+            p.address + r.address,
+            r.reset_value,
+            escape_markdown(&r.description)
+        )?;
+    }
+
+    for r in &registers {
+        writeln!(out)?;
+        writeln!(out, "### {}", r.name)?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "Offset `0x{:x}`, address `0x{:08x}`, reset `0x{:08x}`",
+            r.address,
+            p.address + r.address,
+            r.reset_value
+        )?;
+        if r.description != r.name {
+            writeln!(out)?;
+            writeln!(out, "{}", escape_markdown(&r.description))?;
+        }
+        if r.bit_fields.is_empty() {
+            continue;
+        }
+
+        // the layout of the register as a table with a column per segment
+        let segments = segments(r);
+        let bits: Vec<String> = segments.iter().map(bits_string).collect();
+        let names: Vec<&str> = segments
+            .iter()
+            .map(|s| s.field.map_or("reserved", |f| f.name.as_str()))
+            .collect();
+        writeln!(out)?;
+        writeln!(out, "| {} |", bits.join(" | "))?;
+        writeln!(out, "|{}", " --- |".repeat(segments.len()))?;
+        writeln!(out, "| {} |", names.join(" | "))?;
+
+        writeln!(out)?;
+        writeln!(out, "| Bits | Name | Access | Reset | Description |")?;
+        writeln!(out, "| --- | --- | --- | --- | --- |")?;
+        for segment in &segments {
+            if let Some(field) = segment.field {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | `0x{:x}` | {} |",
+                    bits_string(segment),
+                    field.name,
+                    field.type_.short_name(),
+                    field.reset_value,
+                    escape_markdown(&field.description)
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
@@ -0,0 +1,214 @@
+use std::{collections::HashMap, fs::read_to_string};
+
+use log::warn;
+use serde::Deserialize;
+use xmltree::{Element, XMLNode};
+
+use super::{ChipType, Device};
+
+/// Peripherals are grouped by the prefix of their name, the first matching
+/// prefix decides the group
+const GROUP_PREFIXES: &[(&str, &str)] = &[
+    ("UART", "UART"),
+    ("UHCI", "UHCI"),
+    ("SPI", "SPI"),
+    ("TIMG", "TIMG"),
+    ("TIMER", "TIMER"),
+    ("I2C", "I2C"),
+    ("I2S", "I2S"),
+    ("RTC", "RTC"),
+    ("MCPWM", "PWM"),
+    ("PWM", "PWM"),
+    ("LEDC", "LEDC"),
+    ("GPIO", "GPIO"),
+    ("IO_MUX", "GPIO"),
+    ("SLC", "SLC"),
+    ("INTERRUPT", "INTERRUPT"),
+];
+
+/// Group and naming of a peripheral, overriding the prefix rules
+#[derive(Debug, Default, Deserialize)]
+pub struct GroupOverride {
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub prepend_to_name: Option<String>,
+    #[serde(default)]
+    pub append_to_name: Option<String>,
+}
+
+/// Overrides for a chip, keyed by peripheral name
+pub type GroupOverrides = HashMap<String, GroupOverride>;
+
+/// The group of a peripheral according to the prefix rules, a prefix has to
+/// be followed by an instance number or another part of the name
+pub fn group_name(name: &str) -> Option<String> {
+    GROUP_PREFIXES
+        .iter()
+        .find(|(prefix, _)| {
+            name.strip_prefix(prefix).map_or(false, |rest| {
+                rest.is_empty()
+                    || rest.starts_with('_')
+                    || rest.starts_with(|c: char| c.is_ascii_digit())
+            })
+        })
+        .map(|(_, group)| group.to_string())
+}
+
+/// Loads the overrides of the chip, the file lists them per chip
+pub fn load_group_overrides(file: &str, chip: ChipType) -> GroupOverrides {
+    let data = read_to_string(file).unwrap();
+    let mut overrides: HashMap<String, GroupOverrides> = serde_json::from_str(&data).unwrap();
+
+    overrides.remove(&chip.to_string()).unwrap_or_default()
+}
+
+pub fn apply_group_overrides(device: &mut Device, overrides: GroupOverrides) {
+    let mut unknown_peripherals = vec![];
+
+    for (name, group) in overrides {
+        match device.peripherals.get_mut(&name) {
+            Some(p) => {
+                p.group_name = group.group.or_else(|| p.group_name.take());
+                p.prepend_to_name = group.prepend_to_name.or_else(|| p.prepend_to_name.take());
+                p.append_to_name = group.append_to_name.or_else(|| p.append_to_name.take());
+            }
+            None => unknown_peripherals.push(name),
+        }
+    }
+
+    if unknown_peripherals.len() > 0 {
+        warn!(
+            target: "model",
+            "The following peripherals have a group but don't exist {:?}",
+            unknown_peripherals
+        );
+    }
+}
+
+// The elements of a peripheral which follow `prependToName` and
+// `appendToName` in the schema
+const AFTER_NAME_AFFIXES: &[&str] = &[
+    "headerStructName",
+    "disableCondition",
+    "baseAddress",
+    "addressBlock",
+    "interrupt",
+    "registers",
+];
+
+/// svd-parser can't encode `prependToName` and `appendToName`, so they are
+/// inserted into the encoded peripherals, before the elements the schema
+/// requires to follow them
+pub fn add_name_affixes(device: &Device, svd: &mut Element) {
+    let peripherals = match svd.get_mut_child("peripherals") {
+        Some(peripherals) => peripherals,
+        None => return,
+    };
+
+    for node in peripherals.children.iter_mut() {
+        let element = match node {
+            XMLNode::Element(element) if element.name == "peripheral" => element,
+            _ => continue,
+        };
+        let p = match element
+            .get_child("name")
+            .and_then(|name| name.get_text())
+            .and_then(|name| device.peripherals.get(name.as_ref()))
+        {
+            Some(p) => p,
+            None => continue,
+        };
+
+        let position = element
+            .children
+            .iter()
+            .position(|c| {
+                matches!(c, XMLNode::Element(e) if AFTER_NAME_AFFIXES.contains(&e.name.as_str()))
+            })
+            .unwrap_or(element.children.len());
+
+        let affixes = [
+            ("appendToName", &p.append_to_name),
+            ("prependToName", &p.prepend_to_name),
+        ];
+        for (tag, value) in affixes.iter() {
+            if let Some(value) = value {
+                let mut affix = Element::new(tag);
+                affix.children.push(XMLNode::Text(value.clone()));
+                element.children.insert(position, XMLNode::Element(affix));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Peripheral;
+
+    fn element(name: &str, text: &str) -> XMLNode {
+        let mut element = Element::new(name);
+        element.children.push(XMLNode::Text(text.to_string()));
+        XMLNode::Element(element)
+    }
+
+    fn tags(element: &Element) -> Vec<&str> {
+        element
+            .children
+            .iter()
+            .filter_map(|c| match c {
+                XMLNode::Element(e) => Some(e.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn affixes_follow_the_schema_order() {
+        let peripheral = Peripheral {
+            prepend_to_name: Some("UART_".to_string()),
+            append_to_name: Some("_REG".to_string()),
+            ..Peripheral::default()
+        };
+        let device = Device {
+            chip: ChipType::ESP32,
+            peripherals: vec![("UART0".to_string(), peripheral)]
+                .into_iter()
+                .collect(),
+            interrupts: vec![],
+            memory: vec![],
+        };
+
+        // without a description or group, right after the version
+        let mut encoded = Element::new("peripheral");
+        encoded.children = vec![
+            element("name", "UART0"),
+            element("version", "1"),
+            element("baseAddress", "0x3FF40000"),
+            element("registers", ""),
+        ];
+        let mut peripherals = Element::new("peripherals");
+        peripherals.children.push(XMLNode::Element(encoded));
+        let mut svd = Element::new("device");
+        svd.children.push(XMLNode::Element(peripherals));
+
+        add_name_affixes(&device, &mut svd);
+
+        let peripheral = svd
+            .get_child("peripherals")
+            .and_then(|p| p.get_child("peripheral"))
+            .unwrap();
+        assert_eq!(
+            tags(peripheral),
+            vec![
+                "name",
+                "version",
+                "prependToName",
+                "appendToName",
+                "baseAddress",
+                "registers"
+            ]
+        );
+    }
+}
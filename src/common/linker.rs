@@ -0,0 +1,87 @@
+use std::{
+    fs::{self, File},
+    io::{self, prelude::*, BufWriter},
+    path::Path,
+};
+
+use log::warn;
+use regex::Regex;
+
+use super::{Device, Interrupt, Line, MemoryRegion, Preprocessor};
+
+// Memory regions are defined as a pair of SOC_<NAME>_LOW and SOC_<NAME>_HIGH
+const MEMORY_REGION: &'static str = r"\#define[\s*]+SOC_([0-9A-Za-z_]+)_LOW[\s*]+[^\s]";
+
+/// Finds the memory regions defined by the soc header, the bounds are
+/// evaluated with the macros of the header
+pub fn parse_memory_regions(lines: &[Line], pp: &Preprocessor, target: &str) -> Vec<MemoryRegion> {
+    let re_region = Regex::new(MEMORY_REGION).unwrap();
+    let mut regions: Vec<MemoryRegion> = vec![];
+    let mut invalid_regions = vec![];
+
+    for line in lines {
+        if let Some(captures) = re_region.captures(&line.text) {
+            let name = &captures[1];
+            if regions.iter().any(|r| r.name == name) {
+                continue;
+            }
+
+            let low = pp.eval(&format!("SOC_{}_LOW", name));
+            let high = pp.eval(&format!("SOC_{}_HIGH", name));
+            match (low, high) {
+                (Some(low), Some(high)) if low < high && high <= 1 << 32 => {
+                    regions.push(MemoryRegion {
+                        name: name.to_string(),
+                        origin: low as u32,
+                        length: (high - low) as u32,
+                    })
+                }
+                _ => invalid_regions.push(name.to_string()),
+            }
+        }
+    }
+
+    if invalid_regions.len() > 0 {
+        warn!(
+            target: target,
+            "The following memory regions failed to parse {:?}",
+            invalid_regions
+        );
+    }
+
+    regions
+}
+
+/// Writes `memory.x` with the memory regions of the chip and `device.x` with
+/// a default handler for every interrupt, for bootstrapping runtime crates
+pub fn build_linker_scripts(device: &Device, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let mut regions: Vec<&MemoryRegion> = device.memory.iter().collect();
+    regions.sort_by_key(|r| r.origin);
+
+    let mut out = BufWriter::new(File::create(dir.join("memory.x"))?);
+    writeln!(out, "/* Generated by header2svd, do not edit */")?;
+    writeln!(out, "MEMORY")?;
+    writeln!(out, "{{")?;
+    for region in regions {
+        writeln!(
+            out,
+            "    {} : ORIGIN = 0x{:08X}, LENGTH = 0x{:X}",
+            region.name, region.origin, region.length
+        )?;
+    }
+    writeln!(out, "}}")?;
+
+    let mut interrupts: Vec<&Interrupt> = device.interrupts.iter().collect();
+    interrupts.sort_by_key(|i| i.value);
+    interrupts.dedup_by(|a, b| a.name == b.name);
+
+    let mut out = BufWriter::new(File::create(dir.join("device.x"))?);
+    writeln!(out, "/* Generated by header2svd, do not edit */")?;
+    for interrupt in interrupts {
+        writeln!(out, "PROVIDE({} = DefaultHandler);", interrupt.name)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::{
+    io::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Logs to stderr, so the output can be piped, and counts the warnings for
+/// the summary at the end of a run
+struct Logger {
+    inner: env_logger::Logger,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() == Level::Warn {
+            WARNINGS.fetch_add(1, Ordering::Relaxed);
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Logs everything up to `level`, `filters` and `RUST_LOG` can change the
+/// level per target, e.g. `sdk=debug,doc=off`
+pub fn init_logger(level: LevelFilter, filters: Option<&str>) {
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level).format(|buf, record| {
+        let level = buf.default_styled_level(record.level());
+        writeln!(buf, "[{} {}] {}", level, record.target(), record.args())
+    });
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    if let Some(filters) = filters {
+        builder.parse_filters(filters);
+    }
+
+    let inner = builder.build();
+    log::set_max_level(inner.filter());
+    log::set_boxed_logger(Box::new(Logger { inner })).unwrap();
+}
+
+/// The number of warnings logged so far, including those which were filtered
+/// out by their target
+pub fn warnings() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
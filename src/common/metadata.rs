@@ -0,0 +1,60 @@
+use std::{collections::HashMap, fs::read_to_string};
+
+use log::warn;
+use serde::Deserialize;
+
+use super::{ChipType, Device};
+
+/// Information about a peripheral which the headers don't give
+#[derive(Debug, Default, Deserialize)]
+pub struct PeripheralMetadata {
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Chapter of the technical reference describing the peripheral
+    #[serde(default)]
+    pub trm_chapter: Option<String>,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+/// Metadata of a chip, keyed by peripheral name
+pub type PeripheralMetadataMap = HashMap<String, PeripheralMetadata>;
+
+/// Loads the metadata of the chip, the file lists it per chip
+pub fn load_peripheral_metadata(file: &str, chip: ChipType) -> PeripheralMetadataMap {
+    let data = read_to_string(file).unwrap();
+    let mut metadata: HashMap<String, PeripheralMetadataMap> = serde_json::from_str(&data).unwrap();
+
+    metadata.remove(&chip.to_string()).unwrap_or_default()
+}
+
+/// Merges the metadata into the peripherals, replacing what was derived from
+/// the headers
+pub fn apply_peripheral_metadata(device: &mut Device, metadata: PeripheralMetadataMap) {
+    let mut unknown_peripherals = vec![];
+
+    for (name, meta) in metadata {
+        match device.peripherals.get_mut(&name) {
+            Some(p) => {
+                if let Some(description) = meta.description {
+                    p.description = description;
+                }
+                p.trm_chapter = meta.trm_chapter.or_else(|| p.trm_chapter.take());
+                p.group_name = meta.group.or_else(|| p.group_name.take());
+                p.version = meta.version.or_else(|| p.version.take());
+            }
+            None => unknown_peripherals.push(name),
+        }
+    }
+
+    if unknown_peripherals.len() > 0 {
+        unknown_peripherals.sort();
+        warn!(
+            target: "model",
+            "The following peripherals have metadata but don't exist {:?}",
+            unknown_peripherals
+        );
+    }
+}
@@ -1,16 +1,62 @@
 use std::{
-    collections::HashMap, fs::File, io::prelude::*, ops::RangeInclusive, str::FromStr,
+    collections::{BTreeMap, HashMap},
+    fs::{self, DirEntry, File},
+    io::prelude::*,
+    ops::RangeInclusive,
+    path::Path,
+    str::FromStr,
     string::ToString,
 };
 
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use xmltree::{Element, XMLNode};
+
 use svd_parser::{
-    addressblock::AddressBlock, bitrange::BitRangeType, cpu::CpuBuilder, device::DeviceBuilder,
-    endian::Endian, fieldinfo::FieldInfoBuilder, peripheral::PeripheralBuilder,
-    registerinfo::RegisterInfoBuilder, Access, BitRange, Device as SvdDevice, Field,
-    Register as SvdRegister, RegisterCluster,
+    addressblock::AddressBlock,
+    bitrange::BitRangeType,
+    cpu::CpuBuilder,
+    device::DeviceBuilder,
+    encode::Encode,
+    endian::Endian,
+    fieldinfo::FieldInfoBuilder,
+    peripheral::PeripheralBuilder,
+    registerinfo::RegisterInfoBuilder,
+    writeconstraint::{WriteConstraint as SvdWriteConstraint, WriteConstraintRange},
+    Access, BitRange, Field, Interrupt as SvdInterrupt, Register as SvdRegister, RegisterCluster,
 };
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+mod c_header;
+mod cache;
+mod constraints;
+mod docs;
+mod groups;
+mod linker;
+mod logger;
+mod metadata;
+mod naming;
+mod preprocessor;
+mod tokens;
+mod vendor;
+
+pub use c_header::build_c_headers;
+pub use cache::Cache;
+pub use constraints::add_write_constraints;
+pub use docs::{build_html, build_markdown};
+use groups::{add_name_affixes, group_name};
+pub use groups::{apply_group_overrides, load_group_overrides};
+pub use linker::{build_linker_scripts, parse_memory_regions};
+pub use logger::{init_logger, warnings};
+pub use metadata::{apply_peripheral_metadata, load_peripheral_metadata};
+use naming::normalize_names;
+pub use preprocessor::{Line, Preprocessor};
+pub use tokens::{
+    define_comment, irq_sources, BaseAddr, BitInfo, Mask, MaskKind, RegDef, RegDefKind, Shift,
+    Token,
+};
+use vendor::{interrupt_matrix, vendor_extensions};
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ChipType {
     ESP32,
     ESP32C3,
@@ -18,6 +64,9 @@ pub enum ChipType {
 }
 
 impl ChipType {
+    /// Every supported chip
+    pub const ALL: &'static [ChipType] = &[ChipType::ESP32, ChipType::ESP32C3, ChipType::ESP8266];
+
     pub fn detailed_name(&self) -> String {
         match self {
             ChipType::ESP32 => "Xtensa LX6".to_owned(),
@@ -50,21 +99,172 @@ impl FromStr for ChipType {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+/// Output formats, generated from the intermediate model
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Emit {
+    Svd,
+    ModelJson,
+    ModelYaml,
+    CHeader,
+    Html,
+    Markdown,
+    Linker,
+}
+
+impl Emit {
+    /// File, or directory for outputs consisting of multiple files, written
+    /// to when no output is given
+    pub fn default_output(&self, chip: ChipType) -> String {
+        let chip = chip.to_string().to_lowercase();
+        match self {
+            Emit::Svd => format!("{}.svd", chip),
+            Emit::ModelJson => format!("{}.json", chip),
+            Emit::ModelYaml => format!("{}.yaml", chip),
+            Emit::CHeader => format!("{}-include", chip),
+            Emit::Html => format!("{}-html", chip),
+            Emit::Markdown => format!("{}-md", chip),
+            Emit::Linker => format!("{}-link", chip),
+        }
+    }
+
+    /// The output is split over multiple files, written to a directory
+    pub fn is_directory(&self) -> bool {
+        match self {
+            Emit::CHeader | Emit::Html | Emit::Markdown | Emit::Linker => true,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Emit, Self::Err> {
+        Ok(match s {
+            "svd" => Emit::Svd,
+            "model-json" => Emit::ModelJson,
+            "model-yaml" => Emit::ModelYaml,
+            "c-header" => Emit::CHeader,
+            "html" => Emit::Html,
+            "markdown" => Emit::Markdown,
+            "linker" => Emit::Linker,
+            _ => return Err(format!("Invalid output format: {}", s)),
+        })
+    }
+}
+
+/// Everything parsed for a chip, the model all outputs are generated from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    pub chip: ChipType,
+    pub peripherals: BTreeMap<String, Peripheral>,
+    #[serde(default)]
+    pub interrupts: Vec<Interrupt>,
+    #[serde(default)]
+    pub memory: Vec<MemoryRegion>,
+}
+
+impl Device {
+    /// Puts registers in order of their address and fields in order of their
+    /// offset, ties are broken by name so the order doesn't depend on the
+    /// order the headers were read in
+    pub fn sort(&mut self) {
+        for p in self.peripherals.values_mut() {
+            p.registers
+                .sort_by(|a, b| a.address.cmp(&b.address).then_with(|| a.name.cmp(&b.name)));
+            for r in p.registers.iter_mut() {
+                r.bit_fields.sort_by(|a, b| {
+                    a.bits
+                        .offset()
+                        .cmp(&b.bits.offset())
+                        .then_with(|| a.bits.width().cmp(&b.bits.width()))
+                        .then_with(|| a.name.cmp(&b.name))
+                });
+            }
+        }
+        self.interrupts
+            .sort_by(|a, b| a.value.cmp(&b.value).then_with(|| a.name.cmp(&b.name)));
+        self.memory
+            .sort_by(|a, b| a.origin.cmp(&b.origin).then_with(|| a.name.cmp(&b.name)));
+    }
+
+    /// Sets the width of the registers the headers left unknown to the
+    /// extent of their fields, rounded up to a byte, a half word or a word.
+    /// Fields which don't fit in a register with a given width are reported.
+    pub fn assign_widths(&mut self) {
+        let mut invalid_fields = vec![];
+
+        for (name, p) in self.peripherals.iter_mut() {
+            for r in p.registers.iter_mut() {
+                if r.width == 0 {
+                    let extent = r
+                        .bit_fields
+                        .iter()
+                        .map(|f| f.bits.offset() + f.bits.width())
+                        .max()
+                        .unwrap_or(32);
+                    r.width = match extent {
+                        0..=8 => 8,
+                        9..=16 => 16,
+                        _ => 32,
+                    };
+                }
+
+                for field in &r.bit_fields {
+                    if field.bits.offset() + field.bits.width() > r.width {
+                        invalid_fields.push(format!("{}::{}::{}", name, r.name, field.name));
+                    }
+                }
+            }
+        }
+
+        if invalid_fields.len() > 0 {
+            warn!(
+                target: "model",
+                "The following fields exceed the width of their register {:?}",
+                invalid_fields
+            );
+        }
+    }
+}
+
+/// A memory region as defined in the soc header
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryRegion {
+    pub name: String,
+    pub origin: u32,
+    pub length: u32,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Peripheral {
     pub description: String,
     pub address: u32,
     pub registers: Vec<Register>,
+    /// Group of related peripherals, derived from the name when not set
+    #[serde(default)]
+    pub group_name: Option<String>,
+    /// Prefix of the register names in generated headers
+    #[serde(default)]
+    pub prepend_to_name: Option<String>,
+    /// Suffix of the register names in generated headers
+    #[serde(default)]
+    pub append_to_name: Option<String>,
+    /// Chapter of the technical reference describing the peripheral
+    #[serde(default)]
+    pub trm_chapter: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Interrupt {
     pub name: String,
     pub description: Option<String>,
     pub value: u32,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Register {
     /// Register Name
     pub name: String,
@@ -77,12 +277,26 @@ pub struct Register {
     /// Reset Value
     pub reset_value: u64,
     /// Detailed description
+    #[serde(default)]
     pub detailed_description: Option<String>,
     /// Bit fields
     pub bit_fields: Vec<BitField>,
+    /// Where the register is defined
+    #[serde(default)]
+    pub source: Option<Source>,
+}
+
+impl Register {
+    /// Width in bits, registers of an unknown width are taken to be a word
+    pub fn size(&self) -> u8 {
+        match self.width {
+            0 => 32,
+            width => width,
+        }
+    }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct BitField {
     /// Field Name
     pub name: String,
@@ -94,21 +308,79 @@ pub struct BitField {
     pub reset_value: u32,
     /// Description
     pub description: String,
+    /// Access as written in the source, e.g. `R/WTC/SS`
+    #[serde(default)]
+    pub access: Option<String>,
+    /// Where the field is defined
+    #[serde(default)]
+    pub source: Option<Source>,
+    /// Values which may be written to the field
+    #[serde(default)]
+    pub write_constraint: Option<WriteConstraint>,
 }
 
-#[derive(Debug, Clone)]
+/// Values which may be written to a field, without a constraint svd2rust
+/// only allows writing the bits of fields wider than a bit in `unsafe` code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteConstraint {
+    /// Any value from `min` to `max`
+    Range { min: u64, max: u64 },
+    /// Only the values with a meaning given in the description
+    EnumeratedOnly,
+}
+
+impl From<WriteConstraint> for SvdWriteConstraint {
+    fn from(constraint: WriteConstraint) -> Self {
+        match constraint {
+            WriteConstraint::Range { min, max } => {
+                SvdWriteConstraint::Range(WriteConstraintRange { min, max })
+            }
+            WriteConstraint::EnumeratedOnly => SvdWriteConstraint::UseEnumeratedValues(true),
+        }
+    }
+}
+
+/// Origin of a register or field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Source {
+    /// Line of a header in the SDK
+    Header { file: String, line: usize },
+    /// Row of a register table in the technical reference
+    Doc { table: String, row: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Bits {
     Single(u8),
     Range(RangeInclusive<u8>),
 }
 
+impl Bits {
+    /// Position of the lowest bit
+    pub fn offset(&self) -> u8 {
+        match self {
+            Bits::Single(bit) => *bit,
+            Bits::Range(r) => *r.start(),
+        }
+    }
+
+    /// Number of bits
+    pub fn width(&self) -> u8 {
+        match self {
+            Bits::Single(_) => 1,
+            Bits::Range(r) => r.end() - r.start() + 1,
+        }
+    }
+}
+
 impl Default for Bits {
     fn default() -> Self {
         Bits::Single(0)
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Type {
     // ReadAsZero,
     ReadOnly,
@@ -121,6 +393,17 @@ pub enum Type {
     // WriteToClear,
 }
 
+impl Type {
+    /// Access as written in the esp-idf headers
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            Type::ReadOnly => "RO",
+            Type::ReadWrite => "R/W",
+            Type::WriteOnly => "WO",
+        }
+    }
+}
+
 impl From<Type> for Access {
     fn from(t: Type) -> Self {
         match t {
@@ -167,15 +450,96 @@ pub fn file_to_string(file: &str) -> String {
     data
 }
 
-pub fn build_svd(
-    chip: ChipType,
-    peripherals: HashMap<String, Peripheral>,
-) -> Result<SvdDevice, ()> {
+/// The entries of a directory sorted by name, `read_dir` gives them in no
+/// particular order
+pub fn read_dir_sorted(dir: &str) -> Vec<DirEntry> {
+    let mut entries: Vec<DirEntry> = fs::read_dir(dir).unwrap().filter_map(Result::ok).collect();
+    entries.sort_by_key(|f| f.file_name());
+    entries
+}
+
+/// Loads a model previously written with `--emit model-json` or
+/// `--emit model-yaml`, the format is picked by the file extension
+pub fn load_model(file: &str) -> Result<Device, String> {
+    let data = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let mut device: Device = match Path::new(file).extension().and_then(|ext| ext.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&data).map_err(|e| e.to_string())?,
+        _ => serde_json::from_str(&data).map_err(|e| e.to_string())?,
+    };
+    // the model may have been edited by hand
+    device.sort();
+    device.assign_widths();
+
+    Ok(device)
+}
+
+/// Lists each interrupt on the peripheral raising it, found by its name, or
+/// else on the interrupt matrix. svd2rust generates the interrupt vector from
+/// these, so sources sharing their number with an earlier one are left out.
+fn assign_interrupts(device: &Device) -> HashMap<&str, Vec<SvdInterrupt>> {
+    let fallback =
+        interrupt_matrix(device).or_else(|| device.peripherals.keys().next().map(String::as_str));
+
+    let mut sorted: Vec<&Interrupt> = device.interrupts.iter().collect();
+    sorted.sort_by(|a, b| (a.value, &a.name).cmp(&(b.value, &b.name)));
+
+    // an interrupt number can only be used once
+    let mut sources: Vec<&Interrupt> = vec![];
+    for interrupt in sorted {
+        match sources.last() {
+            Some(kept) if kept.value == interrupt.value => warn!(
+                target: "build",
+                "Leaving out the interrupt {}, its number {} is taken by {}",
+                interrupt.name,
+                interrupt.value,
+                kept.name
+            ),
+            _ => sources.push(interrupt),
+        }
+    }
+
+    let mut interrupts: HashMap<&str, Vec<SvdInterrupt>> = HashMap::new();
+    for interrupt in sources {
+        let peripheral = device
+            .peripherals
+            .keys()
+            .filter(|name| {
+                interrupt
+                    .name
+                    .strip_prefix(name.as_str())
+                    .map_or(false, |rest| !rest.starts_with(char::is_alphabetic))
+            })
+            .max_by_key(|name| name.len())
+            .map(String::as_str)
+            .or(fallback);
+
+        if let Some(peripheral) = peripheral {
+            interrupts
+                .entry(peripheral)
+                .or_default()
+                .push(SvdInterrupt {
+                    name: interrupt.name.clone(),
+                    description: interrupt.description.clone(),
+                    value: interrupt.value,
+                });
+        }
+    }
+
+    interrupts
+}
+
+pub fn build_svd(device: &Device) -> Result<Element, ()> {
+    let mut device = device.clone();
+    normalize_names(&mut device);
+    let device = &device;
+
+    let chip = device.chip;
     let mut svd_peripherals = vec![];
+    let mut interrupts = assign_interrupts(device);
 
-    for (name, p) in peripherals {
+    for (name, p) in &device.peripherals {
         let mut registers = vec![];
-        for r in p.registers {
+        for r in &p.registers {
             let mut fields = vec![];
             for field in &r.bit_fields {
                 let description = if field.description.trim().is_empty() {
@@ -184,17 +548,10 @@ pub fn build_svd(
                     Some(field.description.clone())
                 };
 
-                let bit_range = match &field.bits {
-                    Bits::Single(bit) => BitRange {
-                        offset: u32::from(*bit),
-                        width: 1,
-                        range_type: BitRangeType::OffsetWidth,
-                    },
-                    Bits::Range(r) => BitRange {
-                        offset: u32::from(*r.start()),
-                        width: u32::from(r.end() - r.start() + 1),
-                        range_type: BitRangeType::OffsetWidth,
-                    },
+                let bit_range = BitRange {
+                    offset: u32::from(field.bits.offset()),
+                    width: u32::from(field.bits.width()),
+                    range_type: BitRangeType::OffsetWidth,
                 };
 
                 let field_out = FieldInfoBuilder::default()
@@ -202,6 +559,7 @@ pub fn build_svd(
                     .description(description)
                     .bit_range(bit_range)
                     .access(Some(field.type_.into()))
+                    .write_constraint(field.write_constraint.map(Into::into))
                     .build()
                     .unwrap();
 
@@ -212,7 +570,7 @@ pub fn build_svd(
                 .name(r.name.clone())
                 .description(Some(r.description.clone()))
                 .address_offset(r.address)
-                .size(Some(32))
+                .size(Some(u32::from(r.size())))
                 .reset_value(Some(r.reset_value))
                 .fields(Some(fields))
                 .build()
@@ -221,16 +579,28 @@ pub fn build_svd(
             registers.push(RegisterCluster::Register(SvdRegister::Single(info)));
         }
 
-        let block_size = registers.iter().fold(0, |sum, reg| {
-            sum + match reg {
-                RegisterCluster::Register(r) => r.size.unwrap(),
-                _ => unimplemented!(),
-            }
-        });
+        // the block extends to the end of the last register
+        let block_size = p
+            .registers
+            .iter()
+            .map(|r| r.address + u32::from(r.size()) / 8)
+            .max()
+            .unwrap_or(0);
+
+        // the description is only the name when nothing better was found
+        let description = if p.description.trim().is_empty() || p.description == *name {
+            None
+        } else {
+            Some(p.description.clone())
+        };
 
         let out = PeripheralBuilder::default()
             .name(name.to_owned())
+            .version(p.version.clone())
+            .description(description)
+            .group_name(p.group_name.clone().or_else(|| group_name(name)))
             .base_address(p.address as u64)
+            .interrupt(interrupts.remove(name.as_str()).unwrap_or_default())
             .registers(Some(registers))
             .address_block(Some(AddressBlock {
                 offset: 0x0,
@@ -245,7 +615,7 @@ pub fn build_svd(
 
     svd_peripherals.sort_by(|a, b| a.name.cmp(&b.name));
 
-    println!("Len {}", svd_peripherals.len());
+    debug!(target: "build", "Built {} peripherals", svd_peripherals.len());
 
     let cpu = CpuBuilder::default()
         .name(chip.detailed_name())
@@ -260,7 +630,7 @@ pub fn build_svd(
         .build()
         .unwrap();
 
-    let device = DeviceBuilder::default()
+    let svd = DeviceBuilder::default()
         .name(chip.to_string())
         .version(Some("1.0".to_string()))
         .schema_version(Some("1.0".to_string()))
@@ -272,5 +642,129 @@ pub fn build_svd(
         .build()
         .unwrap();
 
-    Ok(device)
+    // svd-parser encodes with an older version of xmltree, the encoded SVD is
+    // read back to add what it doesn't support
+    let mut xml = vec![];
+    svd.encode()
+        .map_err(|_| ())?
+        .write(&mut xml)
+        .map_err(|_| ())?;
+    let mut svd = Element::parse(&xml[..]).map_err(|_| ())?;
+    // reading drops the namespace prefix of the attribute
+    if let Some(location) = svd.attributes.remove("noNamespaceSchemaLocation") {
+        svd.attributes
+            .insert("xs:noNamespaceSchemaLocation".to_string(), location);
+    }
+    add_name_affixes(device, &mut svd);
+    svd.children
+        .push(XMLNode::Element(vendor_extensions(device)));
+
+    Ok(svd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interrupt(name: &str, value: u32) -> Interrupt {
+        Interrupt {
+            name: name.to_string(),
+            description: None,
+            value,
+        }
+    }
+
+    fn names(interrupts: Option<&Vec<SvdInterrupt>>) -> Vec<&str> {
+        interrupts.map_or(vec![], |i| i.iter().map(|i| i.name.as_str()).collect())
+    }
+
+    #[test]
+    fn interrupts_are_assigned_to_their_peripheral() {
+        let device = Device {
+            chip: ChipType::ESP32,
+            peripherals: ["DPORT", "UART", "UART1", "UHCI0"]
+                .iter()
+                .map(|name| (name.to_string(), Peripheral::default()))
+                .collect(),
+            interrupts: vec![
+                interrupt("UART0_INTR", 34),
+                interrupt("UART1_INTR", 35),
+                interrupt("UHCI0_INTR", 14),
+                interrupt("TG0_T0_LEVEL_INTR", 14),
+                interrupt("TG0_T0_EDGE_INTR", 22),
+                interrupt("UARTX_INTR", 50),
+            ],
+            memory: vec![],
+        };
+
+        let interrupts = assign_interrupts(&device);
+        assert_eq!(names(interrupts.get("UART")), vec!["UART0_INTR"]);
+        assert_eq!(names(interrupts.get("UART1")), vec!["UART1_INTR"]);
+        // the source sharing its number with an earlier one is left out
+        assert_eq!(
+            names(interrupts.get("DPORT")),
+            vec!["TG0_T0_LEVEL_INTR", "TG0_T0_EDGE_INTR", "UARTX_INTR"]
+        );
+        assert_eq!(names(interrupts.get("UHCI0")), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn only_unknown_widths_are_derived() {
+        let register = |width, bits| Register {
+            width,
+            bit_fields: vec![BitField {
+                bits,
+                ..BitField::default()
+            }],
+            ..Register::default()
+        };
+        let peripheral = Peripheral {
+            registers: vec![
+                register(32, Bits::Range(0..=7)),
+                register(0, Bits::Range(0..=7)),
+                register(0, Bits::Single(8)),
+                register(0, Bits::Range(14..=31)),
+                register(8, Bits::Range(0..=9)),
+            ],
+            ..Peripheral::default()
+        };
+        let mut device = Device {
+            chip: ChipType::ESP8266,
+            peripherals: vec![("TIMER".to_string(), peripheral)]
+                .into_iter()
+                .collect(),
+            interrupts: vec![],
+            memory: vec![],
+        };
+
+        device.assign_widths();
+        let widths: Vec<u8> = device.peripherals["TIMER"]
+            .registers
+            .iter()
+            .map(|r| r.width)
+            .collect();
+        assert_eq!(widths, vec![32, 8, 16, 32, 8]);
+    }
+
+    #[test]
+    fn encoded_svd_keeps_the_schema_location() {
+        let device = Device {
+            chip: ChipType::ESP8266,
+            peripherals: vec![("GPIO".to_string(), Peripheral::default())]
+                .into_iter()
+                .collect(),
+            interrupts: vec![],
+            memory: vec![],
+        };
+
+        let svd = build_svd(&device).unwrap();
+        assert_eq!(
+            svd.attributes
+                .get("xs:noNamespaceSchemaLocation")
+                .map(String::as_str),
+            Some("CMSIS-SVD_Schema_1_0.xsd")
+        );
+        assert!(svd.get_child("peripherals").is_some());
+        assert!(svd.get_child("vendorExtensions").is_some());
+    }
 }
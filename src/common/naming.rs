@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use super::{groups::group_name, Device, Peripheral};
+
+// Suffixes of register defines which are not part of the name
+const REGISTER_SUFFIXES: &[&str] = &["_REG", "_ADDRESS"];
+
+/// Prefixes which are redundant in the names of a peripheral's registers and
+/// fields: its own name, the name without instance number and its group
+fn peripheral_prefixes(name: &str, p: &Peripheral) -> Vec<String> {
+    let mut prefixes = vec![
+        format!("{}_", name),
+        format!("{}_", name.trim_end_matches(|c: char| c.is_ascii_digit())),
+    ];
+    if let Some(group) = p.group_name.clone().or_else(|| group_name(name)) {
+        prefixes.push(format!("{}_", group));
+    }
+
+    prefixes.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+    prefixes.dedup();
+    prefixes
+}
+
+/// Strips the longest matching prefix, as long as the rest is still a valid
+/// name, returns the stripped prefix and the rest
+fn strip_prefix<'a>(name: &'a str, prefixes: &[String]) -> Option<(&'a str, &'a str)> {
+    prefixes.iter().find_map(|prefix| {
+        let head = name.get(..prefix.len())?;
+        let rest = &name[prefix.len()..];
+        if head.eq_ignore_ascii_case(prefix) && rest.starts_with(|c: char| c.is_ascii_alphabetic())
+        {
+            Some((head, rest))
+        } else {
+            None
+        }
+    })
+}
+
+fn trim_suffixes(name: &str) -> &str {
+    REGISTER_SUFFIXES
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix).filter(|rest| !rest.is_empty()))
+        .unwrap_or(name)
+}
+
+/// Names which are no longer unique after stripping get their original name
+/// back
+fn resolve_collisions(names: &mut [String], originals: &[String]) {
+    let mut count = HashMap::new();
+    for name in names.iter() {
+        *count.entry(name.to_uppercase()).or_insert(0) += 1;
+    }
+
+    for (name, original) in names.iter_mut().zip(originals) {
+        if count[&name.to_uppercase()] > 1 {
+            *name = original.clone();
+        }
+    }
+}
+
+fn keep_original_name(description: &mut String, original: &str) {
+    if description.trim().is_empty() {
+        *description = original.to_string();
+    } else if !description.contains(original) {
+        *description = format!("{}: {}", original, description);
+    }
+}
+
+/// Strips the peripheral and register prefixes repeated in the names of
+/// registers and fields, e.g. `UART_RXFIFO_FULL_THRHD` of `UART_CONF1` in
+/// `UART0` becomes `RXFIFO_FULL_THRHD` of `CONF1`, the original name is kept
+/// in the description.
+///
+/// When all registers of a peripheral had the same prefix stripped, it is
+/// set as `prependToName` so that headers generated from the SVD get the
+/// original names back.
+pub fn normalize_names(device: &mut Device) {
+    for (name, p) in device.peripherals.iter_mut() {
+        let prefixes = peripheral_prefixes(name, p);
+
+        let originals: Vec<String> = p
+            .registers
+            .iter()
+            .map(|r| trim_suffixes(&r.name).to_string())
+            .collect();
+        let mut stripped = vec![];
+        let mut names: Vec<String> = originals
+            .iter()
+            .map(|original| match strip_prefix(original, &prefixes) {
+                Some((prefix, rest)) => {
+                    stripped.push(prefix.to_uppercase());
+                    rest.to_string()
+                }
+                None => original.clone(),
+            })
+            .collect();
+        resolve_collisions(&mut names, &originals);
+
+        stripped.sort();
+        stripped.dedup();
+        if stripped.len() == 1
+            && !p.registers.is_empty()
+            && names.iter().zip(&originals).all(|(n, o)| n != o)
+            && p.prepend_to_name.is_none()
+        {
+            p.prepend_to_name = stripped.pop();
+        }
+
+        for (r, name) in p.registers.iter_mut().zip(names) {
+            if r.name != name {
+                keep_original_name(&mut r.description, &r.name);
+                r.name = name;
+            }
+
+            let register_prefix = [format!("{}_", r.name)];
+            let field_originals: Vec<String> =
+                r.bit_fields.iter().map(|f| f.name.clone()).collect();
+            let mut field_names: Vec<String> = field_originals
+                .iter()
+                .map(|field| {
+                    let field = strip_prefix(field, &prefixes).map_or(&field[..], |(_, rest)| rest);
+                    // fields may repeat the name of their register as well
+                    let field =
+                        strip_prefix(field, &register_prefix).map_or(field, |(_, rest)| rest);
+                    field.to_string()
+                })
+                .collect();
+            resolve_collisions(&mut field_names, &field_originals);
+
+            for (field, name) in r.bit_fields.iter_mut().zip(field_names) {
+                if field.name != name {
+                    keep_original_name(&mut field.description, &field.name);
+                    field.name = name;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,868 @@
+use std::collections::HashMap;
+
+/// A macro definition, the body is stored as written in the header
+#[derive(Debug, Clone, PartialEq)]
+pub enum Macro {
+    Object(String),
+    Function(Vec<String>, String),
+}
+
+/// A line which is included in the output of the preprocessor
+#[derive(Debug, Clone)]
+pub struct Line {
+    /// Index of the line in the original file
+    pub number: usize,
+    /// Text of the line, with continuation lines joined
+    pub text: String,
+    /// Conditions of the `#if` blocks the line is nested in
+    pub conditions: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Punct(&'static str),
+    Other(char),
+}
+
+struct Block {
+    /// The lines of the current branch are included
+    active: bool,
+    /// One of the branches of the block has been included
+    taken: bool,
+    /// The block the `#if` is nested in is included
+    parent_active: bool,
+    /// Conditions of the branches seen so far
+    conditions: Vec<String>,
+    /// The block is an include guard, its condition isn't reported
+    guard: bool,
+    /// Nothing has been included in the block yet
+    empty: bool,
+}
+
+impl Block {
+    fn condition(&self) -> String {
+        self.conditions.last().cloned().unwrap_or_default()
+    }
+}
+
+const PUNCTUATORS: &[&str] = &[
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "##", "(", ")", "+", "-", "*", "/", "%", "&",
+    "|", "^", "~", "!", "<", ">", "?", ":", ",", "#",
+];
+
+/// A minimal C preprocessor, able to track the macros defined in a header,
+/// evaluate `#if` conditionals and compute constant expressions
+#[derive(Debug, Clone)]
+pub struct Preprocessor {
+    macros: HashMap<String, Macro>,
+    /// Conditionals which could not be evaluated, these are assumed false
+    pub warnings: Vec<String>,
+    /// Lines left out because the conditions around them are false
+    pub excluded: Vec<Line>,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Preprocessor::new()
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        let mut macros = HashMap::new();
+        macros.insert(
+            "BIT".to_string(),
+            Macro::Function(vec!["nr".to_string()], "(1UL << (nr))".to_string()),
+        );
+
+        Preprocessor {
+            macros,
+            warnings: vec![],
+            excluded: vec![],
+        }
+    }
+
+    /// Defines a macro from a `NAME` or `NAME=VALUE` string, as passed to
+    /// `-D` on the command line
+    pub fn define(&mut self, definition: &str) {
+        let (name, value) = match definition.find('=') {
+            Some(i) => (&definition[..i], &definition[i + 1..]),
+            None => (definition, "1"),
+        };
+        self.macros.insert(
+            name.trim().to_string(),
+            Macro::Object(value.trim().to_string()),
+        );
+    }
+
+    /// Defines the options set in an `sdkconfig` file, boolean options are
+    /// defined as `1` and options which are not set are left undefined
+    pub fn define_config(&mut self, config: &str) {
+        for line in config.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line.find('=') {
+                Some(i) if &line[i + 1..] == "y" => self.define(&line[..i]),
+                Some(i) if &line[i + 1..] == "n" => {}
+                Some(_) => self.define(line),
+                None => {}
+            }
+        }
+    }
+
+    /// The macros currently defined, in a stable order, to tell whether two
+    /// preprocessors would evaluate a header the same way
+    pub fn fingerprint(&self) -> String {
+        let mut macros: Vec<String> = self.macros.iter().map(|m| format!("{:?}", m)).collect();
+        macros.sort();
+        macros.join("\n")
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.macros.contains_key(name)
+    }
+
+    /// Runs the preprocessor over a header, recording the macros it defines.
+    /// Returns the lines which are included for the current set of defines,
+    /// without the conditional directives themselves.
+    pub fn process(&mut self, source: &str) -> Vec<Line> {
+        let mut lines = vec![];
+        let mut blocks: Vec<Block> = vec![];
+        let mut in_comment = false;
+
+        let mut physical = source.lines().enumerate();
+        while let Some((number, line)) = physical.next() {
+            let mut text = line.to_string();
+            while text.ends_with('\\') {
+                text.pop();
+                match physical.next() {
+                    Some((_, next)) => text.push_str(next),
+                    None => break,
+                }
+            }
+
+            let was_in_comment = in_comment;
+            let code = strip_comments(&text, &mut in_comment);
+            let active = blocks.last().map_or(true, |b| b.active);
+
+            let directive = code.trim_start();
+            if was_in_comment || !directive.starts_with('#') {
+                let line = Line {
+                    number,
+                    text,
+                    conditions: conditions(&blocks),
+                };
+                if active {
+                    if !code.trim().is_empty() {
+                        blocks.iter_mut().for_each(|b| b.empty = false);
+                    }
+                    lines.push(line);
+                } else {
+                    self.excluded.push(line);
+                }
+                continue;
+            }
+
+            let directive = directive[1..].trim_start();
+            let keyword_len = directive
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or_else(|| directive.len());
+            let (keyword, rest) = directive.split_at(keyword_len);
+            let rest = rest.trim();
+
+            match keyword {
+                "if" | "ifdef" | "ifndef" => {
+                    let condition = match keyword {
+                        "ifdef" => rest.to_string(),
+                        "ifndef" => format!("!{}", rest),
+                        _ => rest.to_string(),
+                    };
+                    let taken = active && self.condition(keyword, rest, number);
+                    blocks.push(Block {
+                        active: taken,
+                        taken,
+                        parent_active: active,
+                        conditions: vec![condition],
+                        guard: false,
+                        empty: true,
+                    });
+                }
+                "elif" => {
+                    if let Some(mut block) = blocks.pop() {
+                        let previous = block.conditions.join(" || ");
+                        block.active = block.parent_active
+                            && !block.taken
+                            && self.condition("if", rest, number);
+                        block.taken |= block.active;
+                        block
+                            .conditions
+                            .push(format!("!({}) && ({})", previous, rest));
+                        blocks.push(block);
+                    }
+                }
+                "else" => {
+                    if let Some(block) = blocks.last_mut() {
+                        let previous = block.conditions.join(" || ");
+                        block.active = block.parent_active && !block.taken;
+                        block.taken = true;
+                        block.conditions = vec![format!("!({})", previous)];
+                    }
+                }
+                "endif" => {
+                    blocks.pop();
+                }
+                _ => {
+                    if !active {
+                        self.excluded.push(Line {
+                            number,
+                            text,
+                            conditions: conditions(&blocks),
+                        });
+                        continue;
+                    }
+
+                    match keyword {
+                        "define" => {
+                            if let Some((name, definition)) = parse_define(rest) {
+                                // `#ifndef FOO_H` directly followed by `#define FOO_H`
+                                if let Some(block) = blocks.last_mut() {
+                                    if block.empty && block.condition() == format!("!{}", name) {
+                                        block.guard = true;
+                                    }
+                                }
+                                self.macros.insert(name, definition);
+                            }
+                        }
+                        "undef" => {
+                            self.macros.remove(rest);
+                        }
+                        _ => {}
+                    }
+
+                    blocks.iter_mut().for_each(|b| b.empty = false);
+                    lines.push(Line {
+                        number,
+                        text,
+                        conditions: conditions(&blocks),
+                    });
+                }
+            }
+        }
+
+        lines
+    }
+
+    fn condition(&mut self, keyword: &str, expr: &str, number: usize) -> bool {
+        match keyword {
+            "ifdef" => self.is_defined(expr),
+            "ifndef" => !self.is_defined(expr),
+            _ => match self.eval_condition(expr) {
+                Some(value) => value != 0,
+                None => {
+                    self.warnings.push(format!("{}: #if {}", number, expr));
+                    false
+                }
+            },
+        }
+    }
+
+    /// Evaluates the expression of an `#if`, unknown identifiers are zero
+    pub fn eval_condition(&self, expr: &str) -> Option<i64> {
+        let tokens = tokenize(expr)?;
+
+        // `defined` has to be resolved before macro expansion
+        let mut resolved = vec![];
+        let mut iter = tokens.into_iter().peekable();
+        while let Some(token) = iter.next() {
+            if token != Token::Ident("defined".to_string()) {
+                resolved.push(token);
+                continue;
+            }
+
+            let parens = iter.peek() == Some(&Token::Punct("("));
+            if parens {
+                iter.next();
+            }
+            let name = match iter.next() {
+                Some(Token::Ident(name)) => name,
+                _ => return None,
+            };
+            if parens && iter.next() != Some(Token::Punct(")")) {
+                return None;
+            }
+            resolved.push(Token::Number(self.is_defined(&name) as i64));
+        }
+
+        let expanded = self.expand(&resolved, &mut vec![])?;
+        Parser::new(expanded, true).parse()
+    }
+
+    /// Evaluates a constant expression, e.g. the value of a define
+    pub fn eval(&self, expr: &str) -> Option<i64> {
+        let tokens = tokenize(expr)?;
+        let expanded = self.expand(&tokens, &mut vec![])?;
+        Parser::new(expanded, false).parse()
+    }
+
+    fn expand(&self, tokens: &[Token], hidden: &mut Vec<String>) -> Option<Vec<Token>> {
+        let mut out = vec![];
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let name = match &tokens[i] {
+                Token::Ident(name) if !hidden.contains(name) => name,
+                token => {
+                    out.push(token.clone());
+                    i += 1;
+                    continue;
+                }
+            };
+
+            match self.macros.get(name) {
+                Some(Macro::Object(body)) => {
+                    hidden.push(name.clone());
+                    out.extend(self.expand(&tokenize(body)?, hidden)?);
+                    hidden.pop();
+                    i += 1;
+                }
+                Some(Macro::Function(params, body))
+                    if tokens.get(i + 1) == Some(&Token::Punct("(")) =>
+                {
+                    let (args, end) = collect_args(tokens, i + 1)?;
+                    if args.len() != params.len() && !(params.is_empty() && args.len() == 1) {
+                        return None;
+                    }
+
+                    let args = args
+                        .iter()
+                        .map(|arg| self.expand(arg, hidden))
+                        .collect::<Option<Vec<_>>>()?;
+
+                    let mut substituted = vec![];
+                    for token in tokenize(body)? {
+                        match &token {
+                            Token::Ident(ident) => match params.iter().position(|p| p == ident) {
+                                Some(p) => substituted.extend(args[p].iter().cloned()),
+                                None => substituted.push(token),
+                            },
+                            _ => substituted.push(token),
+                        }
+                    }
+
+                    hidden.push(name.clone());
+                    out.extend(self.expand(&substituted, hidden)?);
+                    hidden.pop();
+                    i = end + 1;
+                }
+                _ => {
+                    out.push(tokens[i].clone());
+                    i += 1;
+                }
+            }
+        }
+
+        Some(out)
+    }
+}
+
+fn conditions(blocks: &[Block]) -> Vec<String> {
+    blocks
+        .iter()
+        .filter(|b| !b.guard)
+        .map(Block::condition)
+        .collect()
+}
+
+/// Removes `/* */` and `//` comments, keeping track of block comments which
+/// span multiple lines
+fn strip_comments(line: &str, in_comment: &mut bool) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+
+    loop {
+        if *in_comment {
+            match rest.find("*/") {
+                Some(end) => {
+                    *in_comment = false;
+                    rest = &rest[end + 2..];
+                    out.push(' ');
+                }
+                None => return out,
+            }
+        } else {
+            let block = rest.find("/*");
+            let line_comment = rest.find("//");
+            match (block, line_comment) {
+                (Some(b), Some(l)) if l < b => {
+                    out.push_str(&rest[..l]);
+                    return out;
+                }
+                (Some(b), _) => {
+                    out.push_str(&rest[..b]);
+                    *in_comment = true;
+                    rest = &rest[b + 2..];
+                }
+                (None, Some(l)) => {
+                    out.push_str(&rest[..l]);
+                    return out;
+                }
+                (None, None) => {
+                    out.push_str(rest);
+                    return out;
+                }
+            }
+        }
+    }
+}
+
+fn parse_define(rest: &str) -> Option<(String, Macro)> {
+    let mut in_comment = false;
+    let rest = strip_comments(rest, &mut in_comment);
+    let name_len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or_else(|| rest.len());
+    if name_len == 0 {
+        return None;
+    }
+
+    let name = rest[..name_len].to_string();
+    let rest = &rest[name_len..];
+
+    // function-like macros have no space between the name and the parameters
+    if let Some(params) = rest.strip_prefix('(') {
+        let end = params.find(')')?;
+        let names = params[..end]
+            .split(',')
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect();
+        let body = params[end + 1..].trim().to_string();
+        Some((name, Macro::Function(names, body)))
+    } else {
+        Some((name, Macro::Object(rest.trim().to_string())))
+    }
+}
+
+/// Splits the arguments of a macro invocation, `start` is the index of the
+/// opening parenthesis. Returns the arguments and the index of the closing one.
+fn collect_args(tokens: &[Token], start: usize) -> Option<(Vec<Vec<Token>>, usize)> {
+    let mut args = vec![vec![]];
+    let mut depth = 0;
+
+    for (i, token) in tokens.iter().enumerate().skip(start + 1) {
+        match token {
+            Token::Punct("(") => depth += 1,
+            Token::Punct(")") if depth == 0 => return Some((args, i)),
+            Token::Punct(")") => depth -= 1,
+            Token::Punct(",") if depth == 0 => {
+                args.push(vec![]);
+                continue;
+            }
+            _ => {}
+        }
+        args.last_mut().unwrap().push(token.clone());
+    }
+
+    None
+}
+
+fn tokenize(expr: &str) -> Option<Vec<Token>> {
+    let mut in_comment = false;
+    let expr = strip_comments(expr, &mut in_comment);
+    let mut tokens = vec![];
+    let mut rest = expr.as_str();
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or_else(|| rest.len());
+            tokens.push(Token::Number(parse_number(&rest[..len])?));
+            rest = &rest[len..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or_else(|| rest.len());
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            rest = &rest[len..];
+        } else if let Some(p) = PUNCTUATORS.iter().find(|p| rest.starts_with(*p)) {
+            tokens.push(Token::Punct(*p));
+            rest = &rest[p.len()..];
+        } else {
+            tokens.push(Token::Other(c));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    Some(tokens)
+}
+
+fn parse_number(literal: &str) -> Option<i64> {
+    let digits = literal.trim_end_matches(|c| c == 'u' || c == 'U' || c == 'l' || c == 'L');
+
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16).ok()
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8).ok()
+    } else {
+        digits.parse().ok()
+    }
+}
+
+/// Precedence climbing parser for C constant expressions
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// In `#if` expressions identifiers left after expansion evaluate to zero
+    unknown_is_zero: bool,
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>, unknown_is_zero: bool) -> Self {
+        Parser {
+            tokens,
+            pos: 0,
+            unknown_is_zero,
+        }
+    }
+
+    fn parse(mut self) -> Option<i64> {
+        let value = self.ternary()?;
+        if self.pos == self.tokens.len() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn peek(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Punct(p)) => Some(*p),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Option<()> {
+        if self.peek() == Some(punct) {
+            self.pos += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    /// Takes the type of a cast, e.g. `(uint32_t)`, following its opening
+    /// parenthesis
+    fn cast(&mut self) -> Option<Cast> {
+        let mut names = vec![];
+        let mut pos = self.pos;
+        loop {
+            match self.tokens.get(pos)? {
+                Token::Ident(name) => names.push(name.as_str()),
+                Token::Punct(")") => break,
+                _ => return None,
+            }
+            pos += 1;
+        }
+
+        let cast = Cast::from_names(&names)?;
+        self.pos = pos + 1;
+        Some(cast)
+    }
+
+    fn ternary(&mut self) -> Option<i64> {
+        let condition = self.binary(0)?;
+        if self.peek() != Some("?") {
+            return Some(condition);
+        }
+
+        self.pos += 1;
+        let then = self.ternary()?;
+        self.expect(":")?;
+        let otherwise = self.ternary()?;
+
+        Some(if condition != 0 { then } else { otherwise })
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Option<i64> {
+        let mut lhs = self.unary()?;
+
+        while let Some(op) = self.peek() {
+            let precedence = match precedence(op) {
+                Some(p) if p >= min_precedence => p,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.binary(precedence + 1)?;
+
+            lhs = match op {
+                "||" => ((lhs != 0) || (rhs != 0)) as i64,
+                "&&" => ((lhs != 0) && (rhs != 0)) as i64,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "<" => (lhs < rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "<<" => lhs.checked_shl(rhs as u32)?,
+                ">>" => lhs.checked_shr(rhs as u32)?,
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" => lhs.checked_div(rhs)?,
+                "%" => lhs.checked_rem(rhs)?,
+                _ => unreachable!(),
+            };
+        }
+
+        Some(lhs)
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        let token = self.tokens.get(self.pos)?.clone();
+        self.pos += 1;
+
+        match token {
+            Token::Number(n) => Some(n),
+            Token::Ident(_) if self.unknown_is_zero => Some(0),
+            Token::Punct("(") => {
+                if let Some(cast) = self.cast() {
+                    return Some(cast.apply(self.unary()?));
+                }
+                let value = self.ternary()?;
+                self.expect(")")?;
+                Some(value)
+            }
+            Token::Punct("-") => Some(self.unary()?.wrapping_neg()),
+            Token::Punct("+") => self.unary(),
+            // register values are 32 bits wide
+            Token::Punct("~") => Some(!self.unary()? & 0xFFFF_FFFF),
+            Token::Punct("!") => Some((self.unary()? == 0) as i64),
+            _ => None,
+        }
+    }
+}
+
+/// The integer type of a cast, `long` is 32 bits wide on the supported chips
+struct Cast {
+    bits: u32,
+    signed: bool,
+}
+
+impl Cast {
+    fn from_names(names: &[&str]) -> Option<Self> {
+        let (bits, signed) = match names {
+            ["uint8_t"] => (8, false),
+            ["uint16_t"] => (16, false),
+            ["uint32_t"] | ["size_t"] | ["uintptr_t"] => (32, false),
+            ["uint64_t"] => (64, false),
+            ["int8_t"] => (8, true),
+            ["int16_t"] => (16, true),
+            ["int32_t"] | ["intptr_t"] => (32, true),
+            ["int64_t"] => (64, true),
+            _ if names.iter().all(|name| {
+                ["unsigned", "signed", "char", "short", "int", "long"].contains(name)
+            }) =>
+            {
+                let count = |keyword| names.iter().filter(|name| **name == keyword).count();
+                let bits = if count("char") > 0 {
+                    8
+                } else if count("short") > 0 {
+                    16
+                } else if count("long") > 1 {
+                    64
+                } else {
+                    32
+                };
+                (bits, count("unsigned") == 0)
+            }
+            _ => return None,
+        };
+
+        Some(Cast { bits, signed })
+    }
+
+    fn apply(&self, value: i64) -> i64 {
+        if self.bits == 64 {
+            return value;
+        }
+
+        let value = value & ((1 << self.bits) - 1);
+        if self.signed && value >> (self.bits - 1) != 0 {
+            value - (1 << self.bits)
+        } else {
+            value
+        }
+    }
+}
+
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 0,
+        "&&" => 1,
+        "|" => 2,
+        "^" => 3,
+        "&" => 4,
+        "==" | "!=" => 5,
+        "<" | "<=" | ">" | ">=" => 6,
+        "<<" | ">>" => 7,
+        "+" | "-" => 8,
+        "*" | "/" | "%" => 9,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(lines: &[Line]) -> Vec<&str> {
+        lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn object_like_macros_expand() {
+        let mut pp = Preprocessor::new();
+        pp.process("#define BASE 0x3ff40000\n#define OFFSET (BASE + 0x10) /* uart */\n");
+
+        assert_eq!(pp.eval("OFFSET"), Some(0x3ff4_0010));
+        assert_eq!(pp.eval("OFFSET * 2 - BASE"), Some(0x3ff4_0020));
+        assert_eq!(pp.eval("UNKNOWN"), None);
+    }
+
+    #[test]
+    fn function_like_macros_expand() {
+        let mut pp = Preprocessor::new();
+        pp.process(
+            "#define REG_UART_BASE(i) (0x60000000 + (i) * 0xf00)\n\
+             #define SHIFT(value, by) ((value) << (by))\n\
+             #define SELF SELF\n",
+        );
+
+        assert_eq!(pp.eval("REG_UART_BASE(1) + 0x4"), Some(0x6000_0f04));
+        assert_eq!(pp.eval("SHIFT(BIT(1), 4)"), Some(0x20));
+        assert_eq!(pp.eval("SHIFT(1)"), None);
+        // macros are not expanded within themselves
+        assert_eq!(pp.eval("SELF"), None);
+    }
+
+    #[test]
+    fn nested_conditionals() {
+        let mut pp = Preprocessor::new();
+        pp.define("CONFIG_A=2");
+        let lines = pp.process(
+            "#if CONFIG_A == 1\n\
+             one\n\
+             #elif CONFIG_A == 2\n\
+             #ifdef CONFIG_B\n\
+             two_b\n\
+             #else\n\
+             two\n\
+             #endif\n\
+             #else\n\
+             other\n\
+             #endif\n",
+        );
+
+        assert_eq!(texts(&lines), vec!["two"]);
+        assert_eq!(
+            lines[0].conditions,
+            vec![
+                "!(CONFIG_A == 1) && (CONFIG_A == 2)".to_string(),
+                "!(CONFIG_B)".to_string()
+            ]
+        );
+        assert_eq!(
+            pp.excluded
+                .iter()
+                .map(|l| l.text.as_str())
+                .collect::<Vec<_>>(),
+            vec!["one", "two_b", "other"]
+        );
+    }
+
+    #[test]
+    fn defined_is_resolved_before_expansion() {
+        let mut pp = Preprocessor::new();
+        pp.define("A=0");
+        let lines = pp.process(
+            "#if defined(A) && !defined B\n\
+             both\n\
+             #endif\n\
+             #if A || defined(B)\n\
+             either\n\
+             #endif\n",
+        );
+
+        assert_eq!(texts(&lines), vec!["both"]);
+        assert!(pp.warnings.is_empty());
+    }
+
+    #[test]
+    fn include_guards_are_not_conditions() {
+        let header = "#ifndef _UART_H_\n#define _UART_H_\n#define UART_FOO 1\n#endif\n";
+        let mut pp = Preprocessor::new();
+        let lines = pp.process(header);
+
+        assert_eq!(
+            texts(&lines),
+            vec!["#define _UART_H_", "#define UART_FOO 1"]
+        );
+        assert!(lines.iter().all(|line| line.conditions.is_empty()));
+
+        // the guard hides the header from a preprocessor which has seen it
+        assert_eq!(pp.clone().process(header).len(), 0);
+    }
+
+    #[test]
+    fn unknown_conditions_are_reported() {
+        let mut pp = Preprocessor::new();
+        let lines = pp.process("#if FOO(\nfoo\n#endif\n");
+
+        assert!(lines.is_empty());
+        assert_eq!(pp.warnings, vec!["0: #if FOO(".to_string()]);
+    }
+
+    #[test]
+    fn complements_are_32_bits() {
+        let pp = Preprocessor::new();
+
+        assert_eq!(pp.eval("~BIT(3)"), Some(0xFFFF_FFF7));
+        assert_eq!(pp.eval("~0 >> 28"), Some(0xF));
+        assert_eq!(pp.eval("0xFF & ~0x3"), Some(0xFC));
+    }
+
+    #[test]
+    fn negative_values() {
+        let mut pp = Preprocessor::new();
+        let lines = pp.process("#if -1 < 0\nnegative\n#endif\n");
+
+        assert_eq!(texts(&lines), vec!["negative"]);
+        assert_eq!(pp.eval("-0x10"), Some(-16));
+        assert_eq!(pp.eval("4 - 6"), Some(-2));
+    }
+
+    #[test]
+    fn casts() {
+        let pp = Preprocessor::new();
+
+        assert_eq!(pp.eval("(uint32_t)-1"), Some(0xFFFF_FFFF));
+        assert_eq!(pp.eval("(uint8_t)0x1ff"), Some(0xFF));
+        assert_eq!(pp.eval("(int8_t)0xff"), Some(-1));
+        assert_eq!(pp.eval("(unsigned long)(1 << 4) + 1"), Some(17));
+        assert_eq!(pp.eval("(uint32_t)(BIT(31))"), Some(0x8000_0000));
+        assert_eq!(pp.eval("(foo_t)1"), None);
+    }
+}
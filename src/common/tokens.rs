@@ -0,0 +1,485 @@
+use std::collections::BTreeMap;
+
+use log::{info, warn};
+use regex::Regex;
+
+use super::{Bits, Line, Type};
+
+// Interrupt sources are defined by the soc headers, either as defines or as
+// the entries of an enum, where the value may be left out
+const IRQ_SOURCE: &'static str = r"^\s*(\#define\s+)?ETS_([0-9A-Za-z_]+)_SOURCE\b\s*=?\s*\(?\s*(0x[0-9a-fA-F]+|[0-9]+)?\s*\)?\s*,?\s*(.*)$";
+// The last source is followed by the number of sources
+const MAX_IRQ_SOURCE: &'static str = "MAX_INTR";
+
+/// How the address of a register is given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegDefKind {
+    /// Relative to the base of its peripheral, `(DR_REG_UART_BASE + 0x24)`
+    Base,
+    /// Relative to the base of an indexed peripheral,
+    /// `(REG_UART_BASE(i) + 0x24)`
+    Indexed,
+    /// An offset without the peripheral, `0x24`
+    Offset,
+}
+
+/// The define of a register and its address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegDef {
+    pub name: String,
+    pub peripheral: String,
+    /// Expression of the offset within the peripheral
+    pub offset: String,
+    pub kind: RegDefKind,
+}
+
+/// The comment describing a field in the esp-idf headers,
+/// `/* UART_RXFIFO_RD_BYTE : RO ;bitpos:[7:0] ;default: 8'b0 ; */`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitInfo {
+    pub name: String,
+    pub access: String,
+    /// Bits of the field, not set when the position could not be parsed
+    pub bits: Option<Bits>,
+    /// Position as written in the header
+    pub bitpos: String,
+    pub default: String,
+}
+
+/// Which mask of a field a define holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaskKind {
+    /// `FOO`, the mask in place or a single `BIT(n)`
+    Field,
+    /// `FOO_M`, the mask shifted in place
+    Shifted,
+    /// `FOO_V`, the mask shifted down to bit 0
+    Value,
+}
+
+/// A define holding a mask of a field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mask {
+    /// Name of the field, without the suffix of the define
+    pub name: String,
+    /// Expression of the mask
+    pub value: String,
+    pub kind: MaskKind,
+    /// The mask is given as a single `BIT(n)`
+    pub single_bit: bool,
+    /// Access type given in a comment next to the define
+    pub access: Option<Type>,
+}
+
+/// The `FOO_S` define holding the offset of a field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shift {
+    /// Name of the field, without the suffix of the define
+    pub name: String,
+    /// Expression of the offset
+    pub value: String,
+    /// Access type given in a comment next to the define
+    pub access: Option<Type>,
+}
+
+/// The define of a peripheral's base address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaseAddr {
+    /// Name of the define, to be evaluated for the address
+    pub define: String,
+    pub peripheral: String,
+    /// The define takes the index of the instance, `REG_UART_BASE(i)`
+    pub indexed: bool,
+}
+
+/// An interrupt source of the soc header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrqSource {
+    pub name: String,
+    pub value: u32,
+    pub description: Option<String>,
+}
+
+/// The interrupt sources found in the soc headers
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IrqSources {
+    pub sources: Vec<IrqSource>,
+    /// Number of sources given by `ETS_MAX_INTR_SOURCE`
+    pub max: Option<u32>,
+    /// Sources sharing a number with another source, which are kept
+    pub duplicates: Vec<(u32, String)>,
+    /// Defines of sources whose value could not be read
+    pub invalid: Vec<String>,
+}
+
+impl IrqSources {
+    /// Numbers below `ETS_MAX_INTR_SOURCE` without a source, as ranges.
+    /// Without `ETS_MAX_INTR_SOURCE`, as for the ESP8266, the sources are
+    /// not known to be numbered contiguously and no gaps are reported.
+    pub fn gaps(&self) -> Vec<(u32, u32)> {
+        let end = match self.max {
+            Some(end) => end,
+            None => return vec![],
+        };
+
+        let mut gaps: Vec<(u32, u32)> = vec![];
+        for value in 0..end {
+            if self.sources.iter().any(|s| s.value == value) {
+                continue;
+            }
+            match gaps.last_mut() {
+                Some((_, last)) if *last + 1 == value => *last = value,
+                _ => gaps.push((value, value)),
+            }
+        }
+        gaps
+    }
+
+    /// Reports the problems found with the interrupt sources along with how
+    /// many of the sources were found, logged to `target`
+    pub fn report(&self, target: &str) {
+        if self.sources.is_empty() && self.max.is_none() {
+            return;
+        }
+
+        if self.invalid.len() > 0 {
+            warn!(
+                target: target,
+                "The following interrupt sources failed to parse {:?}",
+                self.invalid
+            );
+        }
+
+        if self.duplicates.len() > 0 {
+            warn!(
+                target: target,
+                "The following interrupt sources share their number with another source {:?}",
+                self.duplicates
+            );
+        }
+
+        let gaps: Vec<String> = self
+            .gaps()
+            .into_iter()
+            .map(|(first, last)| {
+                if first == last {
+                    first.to_string()
+                } else {
+                    format!("{}-{}", first, last)
+                }
+            })
+            .collect();
+        if gaps.len() > 0 {
+            warn!(target: target, "The following interrupt sources are missing {:?}", gaps);
+        }
+
+        match self.max {
+            Some(max) => info!(
+                target: target,
+                "Found {} of {} interrupt sources.", self.sources.len(), max),
+            None => info!(
+                target: target,
+                "Found {} interrupt sources, ETS_MAX_INTR_SOURCE is not defined.",
+                self.sources.len()
+            ),
+        }
+    }
+}
+
+/// What a line of a register header holds, as far as the parsers are
+/// concerned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    RegDef(RegDef),
+    BitInfo(BitInfo),
+    Mask(Mask),
+    Shift(Shift),
+    Blank,
+    Other,
+}
+
+/// Finds the interrupt sources in the preprocessed lines of the soc headers,
+/// so sources in excluded `#if` blocks are left out. The description is
+/// taken from the comment following the source, which may span several
+/// lines. A source defined more than once is only kept the first time.
+pub fn irq_sources(lines: &[Line]) -> IrqSources {
+    let re_irq = Regex::new(IRQ_SOURCE).unwrap();
+
+    let mut irqs = IrqSources::default();
+    let mut values: BTreeMap<u32, String> = BTreeMap::new();
+    // value of the previous enum entry, following entries count up from it
+    let mut last_entry: Option<u32> = None;
+
+    let mut i = 0;
+    while i < lines.len() {
+        let captures = match re_irq.captures(&lines[i].text) {
+            Some(captures) => captures,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        let define = captures.get(1).is_some();
+        let name = captures[2].to_string();
+        let (description, next) = comment(lines, i, captures.get(4).unwrap().as_str());
+        i = next;
+
+        let value = match captures.get(3) {
+            Some(value) => parse_value(value.as_str()),
+            None if define => None,
+            None => Some(last_entry.map_or(0, |v| v + 1)),
+        };
+        let value = match value {
+            Some(value) => value,
+            None => {
+                irqs.invalid.push(name);
+                continue;
+            }
+        };
+        if !define {
+            last_entry = Some(value);
+        }
+
+        if name == MAX_IRQ_SOURCE {
+            irqs.max = Some(value);
+            continue;
+        }
+        if irqs.sources.iter().any(|s| s.name == name) {
+            continue;
+        }
+        match values.get(&value) {
+            Some(other) => irqs.duplicates.push((value, format!("{}/{}", other, name))),
+            None => {
+                values.insert(value, name.clone());
+            }
+        }
+
+        irqs.sources.push(IrqSource {
+            name,
+            value,
+            description,
+        });
+    }
+
+    irqs
+}
+
+/// The comment describing the define on line `i`, following it on the same
+/// line or else on the lines right before it. Comments marking sections of
+/// the header, `//Registers Operation {{`, are left out.
+pub fn define_comment(lines: &[Line], i: usize) -> Option<String> {
+    let line = &lines[i].text;
+    let trailing = line.find("//").or_else(|| line.find("/*"));
+
+    let text = match trailing {
+        Some(start) => line[start..].to_string(),
+        None => {
+            let mut comment = vec![];
+            for j in (0..i).rev() {
+                let text = lines[j].text.trim();
+                let is_comment = text.starts_with("//")
+                    || text.starts_with("/*")
+                    || text.starts_with('*')
+                    || text.ends_with("*/");
+                // the comment of a previous define ends there
+                if lines[j].number + 1 != lines[j + 1].number
+                    || text.is_empty()
+                    || text.starts_with('#')
+                    || !is_comment
+                {
+                    break;
+                }
+                comment.push(text);
+            }
+            comment.reverse();
+            comment.join(" ")
+        }
+    };
+    if text.contains("{{") || text.contains("}}") || text.contains("#define") {
+        return None;
+    }
+
+    let text: Vec<&str> = text
+        .split_whitespace()
+        .map(|word| word.trim_matches(&['*', '!', '/', '<'][..]))
+        .filter(|word| !word.is_empty())
+        .collect();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.join(" "))
+    }
+}
+
+fn parse_value(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// The text of the comment starting in `rest` on line `i`, continued on the
+/// following lines until it is closed, along with the line after it
+fn comment(lines: &[Line], i: usize, rest: &str) -> (Option<String>, usize) {
+    let rest = rest.trim();
+    let (text, next) = if let Some(line) = rest.strip_prefix("//") {
+        (line.to_string(), i + 1)
+    } else if let Some(start) = rest.strip_prefix("/*") {
+        let mut text = start.to_string();
+        let mut next = i + 1;
+        while !text.contains("*/") && next < lines.len() {
+            // continued lines may start with a `*`
+            let line = lines[next].text.trim_start();
+            text.push(' ');
+            if line.starts_with("*/") {
+                text.push_str(line);
+            } else {
+                text.push_str(line.trim_start_matches('*'));
+            }
+            next += 1;
+        }
+        let end = text.find("*/").unwrap_or(text.len());
+        text.truncate(end);
+        (text, next)
+    } else {
+        (String::new(), i + 1)
+    };
+
+    // doxygen markers, `/**<`, `/*!<` and `//!<`
+    let text = text.trim_start_matches(&['*', '!', '/', '<'][..]);
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        (None, next)
+    } else {
+        (Some(text), next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::common::Preprocessor;
+
+    fn lines(source: &str) -> Vec<Line> {
+        Preprocessor::new().process(source)
+    }
+
+    #[test]
+    fn sources_in_excluded_blocks_are_left_out() {
+        let irqs = irq_sources(&lines(
+            "#define ETS_FOO_SOURCE 0\n#if 0\n#define ETS_BAR_SOURCE 1\n#else\n#define ETS_BAZ_SOURCE 1\n#endif\n",
+        ));
+        let names: Vec<&str> = irqs.sources.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["FOO", "BAZ"]);
+    }
+
+    #[test]
+    fn gaps_need_the_number_of_sources() {
+        let source = "#define ETS_FOO_SOURCE 1\n#define ETS_BAR_SOURCE 4\n";
+        assert_eq!(irq_sources(&lines(source)).gaps(), vec![]);
+
+        let source = format!("{}#define ETS_MAX_INTR_SOURCE 6\n", source);
+        assert_eq!(
+            irq_sources(&lines(&source)).gaps(),
+            vec![(0, 0), (2, 3), (5, 5)]
+        );
+    }
+
+    #[test]
+    fn define_comments_stop_at_the_previous_define() {
+        let soc = lines(
+            "#define DR_REG_FOO_BASE 0x3ff00000 /* foo */\n/* the bar\n * peripheral */\n#define DR_REG_BAR_BASE 0x3ff01000\n",
+        );
+        assert_eq!(define_comment(&soc, 0), Some("foo".to_string()));
+        assert_eq!(
+            define_comment(&soc, 3),
+            Some("the bar peripheral".to_string())
+        );
+
+        let soc = lines(
+            "#define DR_REG_FOO_BASE 0x3ff00000 /* foo */\n#define DR_REG_BAR_BASE 0x3ff01000\n",
+        );
+        assert_eq!(define_comment(&soc, 1), None);
+    }
+
+    proptest! {
+        #[test]
+        fn irq_source_round_trips(
+            name in "[A-Z][A-Z0-9_]{0,16}[A-Z0-9]",
+            value in 0u32..100,
+            description in "[^*/<!\\s][^*/\n]{0,40}[^*/\\s]",
+        ) {
+            prop_assume!(name != MAX_IRQ_SOURCE);
+            let line = format!(
+                "#define ETS_{}_SOURCE                   {}/**< {}*/",
+                name, value, description
+            );
+            let description = description.split_whitespace().collect::<Vec<_>>().join(" ");
+
+            prop_assert_eq!(
+                irq_sources(&lines(&line)).sources,
+                vec![IrqSource { name, value, description: Some(description) }]
+            );
+        }
+
+        #[test]
+        fn enum_entries_count_up(first in 0u32..100, count in 1usize..10) {
+            let mut source = "typedef enum {\n".to_string();
+            source.push_str(&format!("    ETS_SRC0_SOURCE = {},  /**< first */\n", first));
+            for i in 1..count {
+                source.push_str(&format!("    ETS_SRC{}_SOURCE,\n", i));
+            }
+            source.push_str("    ETS_MAX_INTR_SOURCE,\n} periph_interrput_t;\n");
+
+            let irqs = irq_sources(&lines(&source));
+            let values: Vec<u32> = irqs.sources.iter().map(|s| s.value).collect();
+            prop_assert_eq!(values, (first..first + count as u32).collect::<Vec<_>>());
+            prop_assert_eq!(irqs.max, Some(first + count as u32));
+            prop_assert_eq!(irqs.gaps(), match first {
+                0 => vec![],
+                _ => vec![(0, first - 1)],
+            });
+        }
+
+        #[test]
+        fn multi_line_comments_are_joined(
+            words in prop::collection::vec("[a-z(),.-]{1,8}", 2..6),
+            split in 1usize..5,
+        ) {
+            let split = split.min(words.len() - 1);
+            let source = format!(
+                "#define ETS_FOO_SOURCE 3 /**< {}\n *   {}*/\n#define ETS_BAR_SOURCE 4\n",
+                words[..split].join(" "),
+                words[split..].join(" ")
+            );
+
+            prop_assert_eq!(
+                irq_sources(&lines(&source)).sources,
+                vec![
+                    IrqSource { name: "FOO".to_string(), value: 3, description: Some(words.join(" ")) },
+                    IrqSource { name: "BAR".to_string(), value: 4, description: None },
+                ]
+            );
+        }
+
+        #[test]
+        fn duplicates_are_reported(value in 0u32..100) {
+            let source = format!(
+                "#define ETS_FOO_SOURCE {0}\n#define ETS_FOO_SOURCE {0}\n#define ETS_BAR_SOURCE {0}\n",
+                value
+            );
+            let irqs = irq_sources(&lines(&source));
+
+            prop_assert_eq!(irqs.sources.len(), 2);
+            prop_assert_eq!(irqs.duplicates, vec![(value, "FOO/BAR".to_string())]);
+        }
+
+        #[test]
+        fn irq_sources_never_panic(source in "\\PC*") {
+            let irqs = irq_sources(&lines(&source));
+            irqs.gaps();
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use xmltree::{Element, XMLNode};
+
+use super::{BitField, Device, Interrupt, Peripheral, Register, Source};
+
+// Registers enabling the clocks and resets of the peripherals, without the
+// prefix of the `DPORT` or `SYSTEM` peripheral
+const CLOCK_REGISTERS: &[&str] = &["PERIP_CLK_EN"];
+const RESET_REGISTERS: &[&str] = &["PERIP_RST_EN"];
+
+// Interrupt matrices routing the interrupt sources to the CPU interrupts, as
+// the core, the peripheral and the prefix of its `*_MAP` registers after the
+// prefix of the peripheral
+const INTERRUPT_MATRICES: &[(&str, &str, &str)] = &[
+    ("PRO", "DPORT", "PRO_"),
+    ("APP", "DPORT", "APP_"),
+    ("CORE0", "INTERRUPT_CORE0", ""),
+];
+
+/// The peripheral routing the interrupt sources to the cores
+pub fn interrupt_matrix(device: &Device) -> Option<&'static str> {
+    INTERRUPT_MATRICES
+        .iter()
+        .map(|(_, peripheral, _)| *peripheral)
+        .find(|peripheral| device.peripherals.contains_key(*peripheral))
+}
+
+/// The name of a register without the prefix of its peripheral, which is
+/// only there before the names are normalized
+fn unprefixed<'a>(peripheral: &str, name: &'a str) -> &'a str {
+    name.strip_prefix(peripheral)
+        .and_then(|name| name.strip_prefix('_'))
+        .unwrap_or(name)
+}
+
+fn text_element(name: &str, text: &str) -> XMLNode {
+    let mut element = Element::new(name);
+    element.children.push(XMLNode::Text(text.to_string()));
+    XMLNode::Element(element)
+}
+
+fn source_element(source: &Source) -> XMLNode {
+    let mut element = Element::new("source");
+    match source {
+        Source::Header { file, line } => {
+            element.children.push(text_element("header", file));
+            element
+                .children
+                .push(text_element("line", &line.to_string()));
+        }
+        Source::Doc { table, row } => {
+            element.children.push(text_element("table", table));
+            element.children.push(text_element("row", &row.to_string()));
+        }
+    }
+    XMLNode::Element(element)
+}
+
+/// Finds the bit controlling the peripheral, e.g. `DPORT_UART1_CLK_EN` in
+/// `DPORT_PERIP_CLK_EN` for `UART1`, given as `<register>.<field>`
+fn control_bit(device: &Device, name: &str, registers: &[&str], suffix: &str) -> Option<String> {
+    let (control_name, control) = ["DPORT", "SYSTEM"]
+        .iter()
+        .find_map(|name| Some((*name, device.peripherals.get(*name)?)))?;
+
+    control
+        .registers
+        .iter()
+        .filter(|r| {
+            let r = unprefixed(control_name, &r.name);
+            registers.iter().any(|prefix| r.starts_with(prefix))
+        })
+        .flat_map(|r| r.bit_fields.iter().map(move |f| (r, f)))
+        .find(|(_, f)| {
+            let peripheral = f
+                .name
+                .trim_start_matches("DPORT_")
+                .trim_start_matches("SYSTEM_")
+                .trim_end_matches(suffix);
+            f.name.ends_with(suffix) && (peripheral == name || format!("{}0", peripheral) == name)
+        })
+        .map(|(r, f)| format!("{}.{}", r.name, f.name))
+}
+
+/// Finds the register to program to route the source of the interrupt on
+/// each core, e.g. `DPORT_PRO_UART_INTR_MAP` for `UART0_INTR`. The map
+/// registers are laid out in order of the sources, one word each, starting
+/// with source 0, their names don't always follow the name of the source.
+fn map_registers<'a>(
+    device: &'a Device,
+    interrupt: &Interrupt,
+) -> Vec<(&'static str, &'a Register, u32)> {
+    INTERRUPT_MATRICES
+        .iter()
+        .filter_map(|(core, peripheral, prefix)| {
+            let p = device.peripherals.get(*peripheral)?;
+            let mut registers = p.registers.iter().filter(|r| {
+                let name = unprefixed(peripheral, &r.name);
+                name.starts_with(prefix) && name.ends_with("_MAP")
+            });
+            let first = registers.clone().map(|r| r.address).min()?;
+            let address = first + interrupt.value * 4;
+            let r = registers.find(|r| r.address == address)?;
+            Some((*core, r, p.address + address))
+        })
+        .collect()
+}
+
+fn interrupt_element(device: &Device, interrupt: &Interrupt) -> Option<XMLNode> {
+    let maps = map_registers(device, interrupt);
+    if maps.is_empty() {
+        return None;
+    }
+
+    let mut element = Element::new("interrupt");
+    element.children.push(text_element("name", &interrupt.name));
+    for (core, r, address) in maps {
+        let mut map = Element::new("map");
+        map.children.push(text_element("core", core));
+        map.children.push(text_element("register", &r.name));
+        map.children
+            .push(text_element("address", &format!("0x{:08X}", address)));
+        element.children.push(XMLNode::Element(map));
+    }
+    Some(XMLNode::Element(element))
+}
+
+fn field_element(field: &BitField) -> Option<XMLNode> {
+    if field.access.is_none() && field.source.is_none() {
+        return None;
+    }
+
+    let mut element = Element::new("field");
+    element.children.push(text_element("name", &field.name));
+    if let Some(access) = &field.access {
+        element.children.push(text_element("access", access));
+    }
+    if let Some(source) = &field.source {
+        element.children.push(source_element(source));
+    }
+    Some(XMLNode::Element(element))
+}
+
+fn register_element(r: &Register) -> Option<XMLNode> {
+    let fields: Vec<XMLNode> = r.bit_fields.iter().filter_map(field_element).collect();
+    if r.source.is_none() && fields.is_empty() {
+        return None;
+    }
+
+    let mut element = Element::new("register");
+    element.children.push(text_element("name", &r.name));
+    if let Some(source) = &r.source {
+        element.children.push(source_element(source));
+    }
+    if !fields.is_empty() {
+        let mut fields_element = Element::new("fields");
+        fields_element.children = fields;
+        element.children.push(XMLNode::Element(fields_element));
+    }
+    Some(XMLNode::Element(element))
+}
+
+fn peripheral_element(device: &Device, name: &str, p: &Peripheral) -> Option<XMLNode> {
+    let clock = control_bit(device, name, CLOCK_REGISTERS, "_CLK_EN");
+    let reset = control_bit(device, name, RESET_REGISTERS, "_RST");
+
+    let mut registers: Vec<&Register> = p.registers.iter().collect();
+    registers.sort_by_key(|r| r.address);
+    let registers: Vec<XMLNode> = registers.into_iter().filter_map(register_element).collect();
+
+    if clock.is_none() && reset.is_none() && p.trm_chapter.is_none() && registers.is_empty() {
+        return None;
+    }
+
+    let mut element = Element::new("peripheral");
+    element.children.push(text_element("name", name));
+    if let Some(chapter) = &p.trm_chapter {
+        element.children.push(text_element("trmChapter", chapter));
+    }
+    if let Some(clock) = clock {
+        element.children.push(text_element("clock", &clock));
+    }
+    if let Some(reset) = reset {
+        element.children.push(text_element("reset", &reset));
+    }
+    if !registers.is_empty() {
+        let mut registers_element = Element::new("registers");
+        registers_element.children = registers;
+        element.children.push(XMLNode::Element(registers_element));
+    }
+    Some(XMLNode::Element(element))
+}
+
+/// Metadata without a place in the SVD format: where every register and field
+/// was parsed from, the original access strings, the technical reference
+/// chapter and the bits controlling the clock and reset of each peripheral and
+/// the interrupt matrix registers mapping each interrupt.
+///
+/// The schema only allows vendor extensions on the device, so the extensions
+/// of the peripherals, registers and fields are nested within it, mirroring
+/// the structure of the SVD.
+pub fn vendor_extensions(device: &Device) -> Element {
+    let mut extensions = Element::new("vendorExtensions");
+    extensions.children.push(text_element(
+        "generator",
+        &format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+    ));
+
+    let mut peripherals: Vec<_> = device.peripherals.iter().collect();
+    peripherals.sort_by(|a, b| a.0.cmp(b.0));
+    let peripherals: Vec<XMLNode> = peripherals
+        .into_iter()
+        .filter_map(|(name, p)| peripheral_element(device, name, p))
+        .collect();
+    if !peripherals.is_empty() {
+        let mut peripherals_element = Element::new("peripherals");
+        peripherals_element.children = peripherals;
+        extensions
+            .children
+            .push(XMLNode::Element(peripherals_element));
+    }
+
+    let mut interrupts: Vec<&Interrupt> = device.interrupts.iter().collect();
+    interrupts.sort_by_key(|i| i.value);
+    let interrupts: Vec<XMLNode> = interrupts
+        .into_iter()
+        .filter_map(|i| interrupt_element(device, i))
+        .collect();
+    if !interrupts.is_empty() {
+        let mut interrupts_element = Element::new("interrupts");
+        interrupts_element.children = interrupts;
+        extensions
+            .children
+            .push(XMLNode::Element(interrupts_element));
+    }
+
+    extensions
+}
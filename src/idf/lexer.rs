@@ -0,0 +1,197 @@
+use regex::Regex;
+
+use crate::common::{BaseAddr, BitInfo, Bits, RegDef, RegDefKind, Token};
+
+// Regexes to find all the peripheral addresses
+const REG_BASE: &'static str = r"\#define[\s*]+DR_REG_([0-9A-Za-z_]+)_BASE[\s*]+[^\s]";
+const REG_DEF: &'static str = r"\#define[\s*]+([^\s*]+)_REG[\s*]+\(DR_REG_(.*)_BASE \+ (.*)\)";
+const REG_DEF_INDEX: &'static str =
+    r"\#define[\s*]+([^\s*]+)_REG\(i\)[\s*]+\(REG_([0-9A-Za-z_]+)_BASE[\s*]*\(i\) \+ (.*?)\)";
+const REG_BIT_INFO: &'static str = r"/\*[\s]+([0-9A-Za-z_]+)[\s]+:[\s]+([0-9A-Za-z_/]+)[\s]+;bitpos:\[(.*)\][\s];default:[\s]+(.*)[\s];[\s]\*/";
+
+/// Recognises the lines of the esp-idf headers
+pub struct Lexer {
+    re_base: Regex,
+    re_reg: Regex,
+    re_reg_index: Regex,
+    re_bit_info: Regex,
+}
+
+impl Lexer {
+    pub fn new() -> Self {
+        Lexer {
+            re_base: Regex::new(REG_BASE).unwrap(),
+            re_reg: Regex::new(REG_DEF).unwrap(),
+            re_reg_index: Regex::new(REG_DEF_INDEX).unwrap(),
+            re_bit_info: Regex::new(REG_BIT_INFO).unwrap(),
+        }
+    }
+
+    pub fn token(&self, line: &str) -> Token {
+        if line.trim().is_empty() {
+            Token::Blank
+        } else if let Some(reg) = self.reg_def(line) {
+            Token::RegDef(reg)
+        } else if let Some(info) = self.bit_info(line) {
+            Token::BitInfo(info)
+        } else {
+            Token::Other
+        }
+    }
+
+    fn reg_def(&self, line: &str) -> Option<RegDef> {
+        let (m, kind) = match self.re_reg.captures(line) {
+            Some(m) => (m, RegDefKind::Base),
+            None => (self.re_reg_index.captures(line)?, RegDefKind::Indexed),
+        };
+
+        Some(RegDef {
+            name: m[1].to_string(),
+            peripheral: m[2].to_string(),
+            offset: m[3].to_string(),
+            kind,
+        })
+    }
+
+    fn bit_info(&self, line: &str) -> Option<BitInfo> {
+        let m = self.re_bit_info.captures(line)?;
+        let mut bits = m[3].split(':');
+        let bits = match (bits.next(), bits.next(), bits.next()) {
+            (Some(h), Some(l), None) => match (h.parse(), l.parse()) {
+                (Ok(h), Ok(l)) => Some(Bits::Range(l..=h)),
+                _ => None,
+            },
+            (Some(b), None, None) => b.parse().ok().map(Bits::Single),
+            _ => None,
+        };
+
+        Some(BitInfo {
+            name: m[1].to_string(),
+            access: m[2].to_string(),
+            bits,
+            bitpos: m[3].to_string(),
+            default: m[4].to_string(),
+        })
+    }
+
+    pub fn base_addr(&self, line: &str) -> Option<BaseAddr> {
+        let m = self.re_base.captures(line)?;
+
+        Some(BaseAddr {
+            define: format!("DR_REG_{}_BASE", &m[1]),
+            peripheral: m[1].to_string(),
+            indexed: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const NAME: &str = "[A-Z][A-Z0-9]{0,8}(_[A-Z0-9]{1,8}){0,3}";
+    const ACCESS: &str = "(RO|R/W|WO|R/W/SC|R/WTC/SS)";
+
+    proptest! {
+        #[test]
+        fn reg_def_round_trips(name in NAME, peripheral in NAME, offset in 0u32..0x1000) {
+            let line = format!(
+                "#define {}_REG          (DR_REG_{}_BASE + 0x{:03X})",
+                name, peripheral, offset
+            );
+
+            prop_assert_eq!(
+                Lexer::new().token(&line),
+                Token::RegDef(RegDef {
+                    name,
+                    peripheral,
+                    offset: format!("0x{:03X}", offset),
+                    kind: RegDefKind::Base,
+                })
+            );
+        }
+
+        #[test]
+        fn indexed_reg_def_round_trips(name in NAME, peripheral in "[A-Z][A-Z0-9]{0,8}", offset in 0u32..0x1000) {
+            let line = format!(
+                "#define {}_REG(i)          (REG_{}_BASE(i) + 0x{:x})",
+                name, peripheral, offset
+            );
+
+            prop_assert_eq!(
+                Lexer::new().token(&line),
+                Token::RegDef(RegDef {
+                    name,
+                    peripheral,
+                    offset: format!("0x{:x}", offset),
+                    kind: RegDefKind::Indexed,
+                })
+            );
+        }
+
+        #[test]
+        fn bit_info_round_trips(
+            name in NAME,
+            access in ACCESS,
+            (low, high) in (0u8..32).prop_flat_map(|low| (Just(low), low..32)),
+            default in "[0-9]{1,2}'[bh][0-9]{1,4}",
+        ) {
+            let (bits, bitpos) = if low == high {
+                (Bits::Single(low), low.to_string())
+            } else {
+                (Bits::Range(low..=high), format!("{}:{}", high, low))
+            };
+            let line = format!(
+                "/* {} : {} ;bitpos:[{}] ;default: {} ; */",
+                name, access, bitpos, default
+            );
+
+            prop_assert_eq!(
+                Lexer::new().token(&line),
+                Token::BitInfo(BitInfo {
+                    name,
+                    access,
+                    bits: Some(bits),
+                    bitpos,
+                    default,
+                })
+            );
+        }
+
+        #[test]
+        fn invalid_bitpos_is_kept(name in NAME, bitpos in "[0-9]{1,2}:[0-9]{1,2}:[0-9]{1,2}") {
+            let line = format!("/* {} : R/W ;bitpos:[{}] ;default: 1'b0 ; */", name, bitpos);
+
+            match Lexer::new().token(&line) {
+                Token::BitInfo(info) => {
+                    prop_assert_eq!(info.bits, None);
+                    prop_assert_eq!(info.bitpos, bitpos);
+                }
+                token => prop_assert!(false, "{:?}", token),
+            }
+        }
+
+        #[test]
+        fn base_addr_round_trips(peripheral in NAME, address in any::<u32>()) {
+            let line = format!("#define DR_REG_{}_BASE          0x{:08x}", peripheral, address);
+
+            prop_assert_eq!(
+                Lexer::new().base_addr(&line),
+                Some(BaseAddr {
+                    define: format!("DR_REG_{}_BASE", peripheral),
+                    peripheral,
+                    indexed: false,
+                })
+            );
+        }
+
+        #[test]
+        fn lexing_never_panics(line in "\\PC*") {
+            let lexer = Lexer::new();
+            lexer.token(&line);
+            lexer.base_addr(&line);
+        }
+    }
+}
@@ -1,21 +1,22 @@
-use std::{collections::HashMap, fs::File, io::BufWriter, str::FromStr};
+use std::{collections::BTreeMap, path::Path, str::FromStr};
 
+use log::{debug, warn};
+use rayon::prelude::*;
 use regex::Regex;
-use svd_parser::encode::Encode;
+use serde::{Deserialize, Serialize};
 
 use crate::common::{
-    build_svd, file_to_string, BitField, Bits, ChipType, Interrupt, Peripheral, Register, Type,
+    add_write_constraints, define_comment, file_to_string, irq_sources, parse_memory_regions,
+    read_dir_sorted, BitField, Cache, ChipType, Device, Interrupt, Peripheral, Preprocessor,
+    Register, Source, Token, Type,
 };
 
-// Regexes to find all the peripheral addresses
-const REG_BASE: &'static str = r"\#define[\s*]+DR_REG_(.*)_BASE[\s*]+0x([0-9a-fA-F]+)";
-const REG_DEF: &'static str = r"\#define[\s*]+([^\s*]+)_REG[\s*]+\(DR_REG_(.*)_BASE \+ (.*)\)";
-const REG_DEF_INDEX: &'static str =
-    r"\#define[\s*]+([^\s*]+)_REG\(i\)[\s*]+\(REG_([0-9A-Za-z_]+)_BASE[\s*]*\(i\) \+ (.*?)\)";
-const REG_BIT_INFO: &'static str = r"/\*[\s]+([0-9A-Za-z_]+)[\s]+:[\s]+([0-9A-Za-z_/]+)[\s]+;bitpos:\[(.*)\][\s];default:[\s]+(.*)[\s];[\s]\*/";
+mod lexer;
+
+use lexer::Lexer;
+
+// Descriptions may span multiple lines, they are matched on the joined lines
 const REG_DESC: &'static str = r"\*description:\s(.*[\n|\r|\r\n]?.*)\*/";
-const INTERRUPTS: &'static str =
-    r"\#define[\s]ETS_([0-9A-Za-z_/]+)_SOURCE[\s]+([0-9]+)/\*\*<\s([0-9A-Za-z_/\s,]+)\*/";
 
 enum State {
     FindReg,
@@ -24,21 +25,19 @@ enum State {
     CheckEnd(String, Register),
 }
 
-fn parse_idf(chip: &ChipType) -> HashMap<String, Peripheral> {
-    let mut peripherals = HashMap::new();
+fn parse_idf(chip: &ChipType, pp: &Preprocessor, cache: &Cache) -> Device {
+    let mut peripherals = BTreeMap::new();
     let mut interrupts = vec![];
 
     let mut invalid_bit_fields = vec![];
     let mut invalid_files = vec![];
     let mut invalid_peripherals = vec![];
     let mut invalid_registers = vec![];
+    let mut invalid_types = vec![];
+    let mut invalid_conditionals = vec![];
 
-    let re_base = Regex::new(REG_BASE).unwrap();
-    let re_reg = Regex::new(REG_DEF).unwrap();
-    let re_reg_index = Regex::new(REG_DEF_INDEX).unwrap();
-    let re_reg_bit_info = Regex::new(REG_BIT_INFO).unwrap();
+    let lexer = Lexer::new();
     let re_reg_desc = Regex::new(REG_DESC).unwrap();
-    let re_interrupts = Regex::new(INTERRUPTS).unwrap();
 
     let soc_base_path = format!(
         "esp-idf/components/soc/{}/include/soc",
@@ -48,18 +47,39 @@ fn parse_idf(chip: &ChipType) -> HashMap<String, Peripheral> {
     let filename = format!("{}/{}", soc_base_path, "soc.h");
     let soc_h = file_to_string(&filename);
 
-    for captures in re_interrupts.captures_iter(soc_h.as_str()) {
-        let name = &captures[1];
-        let index = &captures[2];
-        let desc = &captures[3];
-
-        let intr = Interrupt {
-            name: name.to_string(),
-            description: Some(desc.to_string()),
-            value: index.parse().unwrap(),
-        };
-        interrupts.push(intr);
-        // println!("{:#?}", intr);
+    let mut soc_pp = pp.clone();
+    let soc_lines = soc_pp.process(&soc_h);
+    invalid_conditionals.extend(
+        soc_pp
+            .warnings
+            .drain(..)
+            .map(|w| format!("{}:{}", filename, w)),
+    );
+    let memory = parse_memory_regions(&soc_lines, &soc_pp, "idf");
+
+    // newer versions of esp-idf enumerate the interrupt sources in
+    // periph_defs.h instead
+    let mut irq_lines = soc_lines.clone();
+    let periph_defs = format!("{}/{}", soc_base_path, "periph_defs.h");
+    if Path::new(&periph_defs).exists() {
+        let mut defs_pp = soc_pp.clone();
+        irq_lines.extend(defs_pp.process(&file_to_string(&periph_defs)));
+        invalid_conditionals.extend(
+            defs_pp
+                .warnings
+                .drain(..)
+                .map(|w| format!("{}:{}", periph_defs, w)),
+        );
+    }
+
+    let irqs = irq_sources(&irq_lines);
+    irqs.report("idf");
+    for source in irqs.sources {
+        interrupts.push(Interrupt {
+            name: source.name,
+            description: source.description,
+            value: source.value,
+        });
     }
 
     /*
@@ -81,174 +101,281 @@ fn parse_idf(chip: &ChipType) -> HashMap<String, Peripheral> {
     }
 
     /* Peripheral base addresses */
-    for captures in re_base.captures_iter(soc_h.as_str()) {
-        let peripheral = &captures[1];
-        let address = &captures[2];
-
-        let mut p = Peripheral::default();
-        p.address = u32::from_str_radix(address, 16).unwrap();
-        p.description = peripheral.to_string();
-
-        peripherals.insert(peripheral.to_string(), p);
+    for (i, base) in soc_lines
+        .iter()
+        .enumerate()
+        .filter_map(|(i, line)| Some((i, lexer.base_addr(&line.text)?)))
+    {
+        if let Some(address) = soc_pp.eval(&base.define) {
+            let mut p = Peripheral::default();
+            p.address = address as u32;
+            p.description =
+                define_comment(&soc_lines, i).unwrap_or_else(|| base.peripheral.clone());
+
+            peripherals.insert(base.peripheral, p);
+        } else {
+            invalid_peripherals.push(base.peripheral);
+        }
     }
 
-    std::fs::read_dir(soc_base_path)
-        .unwrap()
-        .filter_map(Result::ok)
-        .filter(|f| f.path().to_str().unwrap().ends_with("_reg.h"))
-        .for_each(|f| {
-            let name = f.path();
-            let name = name.to_str().unwrap();
-
-            let mut buffer = vec![];
+    let files: Vec<String> = read_dir_sorted(&soc_base_path)
+        .into_iter()
+        .map(|f| f.path().to_str().unwrap().to_string())
+        .filter(|name| name.ends_with("_reg.h"))
+        .collect();
+
+    // the headers are independent of each other, the results are merged in
+    // the order of the files to keep the output stable. A header only has to
+    // be parsed again when it or the macros it's evaluated against changed.
+    let context = soc_pp.fingerprint();
+    let headers: Vec<Header> = files
+        .par_iter()
+        .map(|name| {
             let file_data = file_to_string(name);
-
-            let mut something_found = false;
-            let mut state = State::FindReg;
-            for (i, line) in file_data.lines().enumerate() {
-                loop {
-                    match state {
-                        State::FindReg => {
-                            let captures = if re_reg.is_match(line) {
-                                re_reg.captures(line)
-                            } else if re_reg_index.is_match(line) {
-                                re_reg_index.captures(line)
-                            } else {
-                                None
-                            };
-
-                            if let Some(m) = captures {
-                                let reg_name = &m[1];
-                                let pname = &m[2];
-                                let offset = &m[3].trim_start_matches("0x");
-
-                                if let Ok(addr) = u32::from_str_radix(offset, 16) {
-                                    let mut r = Register::default();
-                                    r.name = reg_name.to_string();
-                                    r.description = reg_name.to_string();
-                                    r.address = addr;
-                                    state = State::FindBitFieldInfo(pname.to_string(), r);
-                                } else {
-                                    invalid_registers.push(reg_name.to_string());
-                                }
-                            }
-                            break; // next line
-                        }
-                        State::FindBitFieldInfo(ref mut pname, ref mut reg) => {
-                            something_found = true;
-                            if let Some(m) = re_reg_bit_info.captures(line) {
-                                let bf_name = &m[1];
-                                let access_type = &m[2]; // TODO
-                                let bits = &mut m[3].split(':');
-                                let _default_val = &m[4]; // TODO
-                                let bits = match (bits.next(), bits.next()) {
-                                    (Some(h), Some(l)) => {
-                                        Bits::Range(l.parse().unwrap()..=h.parse().unwrap())
-                                    }
-                                    (Some(b), None) => Bits::Single(b.parse().unwrap()),
-                                    _ => {
-                                        // println!("Failed to parse bitpos {}", &m[3]);
-                                        invalid_bit_fields
-                                            .push((bf_name.to_string(), m[3].to_string()));
-                                        continue;
-                                    }
-                                };
-
-                                let bf = BitField {
-                                    name: bf_name.to_string(),
-                                    bits,
-                                    type_: Type::from_str(access_type).unwrap_or_else(|s| {
-                                        println!("{}", s);
-                                        Type::default()
-                                    }),
-                                    reset_value: 0,
-                                    ..Default::default()
-                                };
-                                state = State::FindDescription(pname.clone(), reg.clone(), bf);
-                            } else {
-                                println!("Failed to match reg info at {}:{} ('{}')", name, i, line);
-                                state = State::FindReg;
-                            }
-                            break; // next line
-                        }
-                        State::FindDescription(ref mut pname, ref mut reg, ref mut bf) => {
-                            buffer.push(line);
-                            if let Some(_m) = re_reg_desc.captures(buffer.join("").as_str()) {
-                                buffer.clear();
-                                reg.bit_fields.push(bf.clone()); // add the bit field to the reg
-                                state = State::CheckEnd(pname.clone(), reg.clone());
-                            }
-                            break; // next line
-                        }
-                        State::CheckEnd(ref mut pname, ref mut reg) => {
-                            if line.is_empty() {
-                                // we're done with this register
-                                if let Some(p) = peripherals.get_mut(&pname.to_string()) {
-                                    p.registers.push(reg.clone());
-                                } else {
-                                    // TODO indexed peripherals wont come up here
-                                    println!("No peripheral called {}", pname.to_string());
-                                    invalid_peripherals.push(pname.to_string());
-                                }
-                                state = State::FindReg;
-                                break; // next line
-                            } else if re_reg_bit_info.is_match(line) {
-                                // we've found the next bit field in the reg
-                                state = State::FindBitFieldInfo(pname.clone(), reg.clone());
-                            } else {
-                                break; // next line
-                            }
-                        }
-                    }
-                }
-            }
-
-            // log if nothing was parsed in this file
-            if !something_found {
-                invalid_files.push(String::from(name))
+            let key = Cache::key(&[name, &context, &file_data]);
+            cache.get("header", &key).unwrap_or_else(|| {
+                let header = parse_header(name, &file_data, &soc_pp, &lexer, &re_reg_desc);
+                cache.put("header", &key, &header);
+                header
+            })
+        })
+        .collect();
+
+    for (name, header) in files.iter().zip(headers) {
+        header
+            .messages
+            .iter()
+            .for_each(|m| debug!(target: "idf", "{}", m));
+
+        for (pname, reg) in header.registers {
+            if let Some(p) = peripherals.get_mut(&pname) {
+                p.registers.push(reg);
+            } else {
+                // TODO indexed peripherals wont come up here
+                debug!(target: "idf", "No peripheral called {}", pname);
+                invalid_peripherals.push(pname);
             }
-        });
+        }
+
+        // log if nothing was parsed in this file
+        if !header.something_found {
+            invalid_files.push(name.clone())
+        }
+
+        invalid_registers.extend(header.invalid_registers);
+        invalid_bit_fields.extend(header.invalid_bit_fields);
+        invalid_types.extend(header.invalid_types);
+        invalid_conditionals.extend(
+            header
+                .warnings
+                .into_iter()
+                .map(|w| format!("{}:{}", name, w)),
+        );
+    }
 
-    println!("Parsed idf for peripherals information.");
+    debug!(target: "idf", "Parsed the headers for peripherals information.");
 
     if invalid_files.len() > 0 {
-        println!(
+        warn!(
+            target: "idf",
             "The following files contained no parsable information {:?}",
             invalid_files
         );
     }
 
     if invalid_peripherals.len() > 0 {
-        println!(
+        warn!(
+            target: "idf",
             "The following peripherals failed to parse {:?}",
             invalid_peripherals
         );
     }
 
     if invalid_registers.len() > 0 {
-        println!(
+        warn!(
+            target: "idf",
             "The following registers failed to parse {:?}",
             invalid_registers
         );
     }
 
     if invalid_bit_fields.len() > 0 {
-        println!(
+        warn!(
+            target: "idf",
             "The following bit_fields failed to parse {:?}",
             invalid_bit_fields
         );
     }
 
-    // println!("Interrupt information: {:#?}", interrupts);
+    if invalid_types.len() > 0 {
+        warn!(
+            target: "idf",
+            "The following bit_fields have an unknown access type {:?}",
+            invalid_types
+        );
+    }
+
+    if invalid_conditionals.len() > 0 {
+        warn!(
+            target: "idf",
+            "The following conditionals could not be evaluated {:?}",
+            invalid_conditionals
+        );
+    }
 
-    peripherals
+    Device {
+        chip: *chip,
+        peripherals,
+        interrupts,
+        memory,
+    }
+}
+
+/// Everything parsed from a single register header
+#[derive(Default, Serialize, Deserialize)]
+struct Header {
+    /// Registers along with the name of their peripheral
+    registers: Vec<(String, Register)>,
+    something_found: bool,
+    invalid_registers: Vec<String>,
+    invalid_bit_fields: Vec<(String, String)>,
+    /// Fields with an access type which isn't known
+    invalid_types: Vec<String>,
+    /// Conditionals which could not be evaluated
+    warnings: Vec<String>,
+    /// Details of the problems to log, kept so they are logged for cached
+    /// headers too
+    messages: Vec<String>,
 }
 
-pub fn create_svd(chip: ChipType) {
-    let peripherals = parse_idf(&chip);
-    let filename = format!("{}.svd", chip.to_string().to_lowercase());
-    let svd = build_svd(chip, peripherals).unwrap();
+fn parse_header(
+    name: &str,
+    file_data: &str,
+    soc_pp: &Preprocessor,
+    lexer: &Lexer,
+    re_reg_desc: &Regex,
+) -> Header {
+    let mut header = Header::default();
+    let mut buffer = String::new();
+
+    let mut pp = soc_pp.clone();
+    let lines = pp.process(file_data);
+
+    let mut state = State::FindReg;
+    for line in &lines {
+        let (i, text) = (line.number, line.text.as_str());
+        let token = lexer.token(text);
+
+        loop {
+            match state {
+                State::FindReg => {
+                    if let Token::RegDef(def) = &token {
+                        if let Some(addr) = pp.eval(&def.offset) {
+                            let mut r = Register::default();
+                            r.name = def.name.clone();
+                            r.description = def.name.clone();
+                            r.address = addr as u32;
+                            r.source = Some(Source::Header {
+                                file: name.to_string(),
+                                line: i,
+                            });
+                            state = State::FindBitFieldInfo(def.peripheral.clone(), r);
+                        } else {
+                            header.invalid_registers.push(def.name.clone());
+                        }
+                    }
+                    break; // next line
+                }
+                State::FindBitFieldInfo(ref mut pname, ref mut reg) => {
+                    header.something_found = true;
+                    match &token {
+                        Token::BitInfo(info) => match &info.bits {
+                            Some(bits) => {
+                                let bf = BitField {
+                                    name: info.name.clone(),
+                                    bits: bits.clone(),
+                                    type_: Type::from_str(&info.access).unwrap_or_else(|_| {
+                                        header.invalid_types.push(format!(
+                                            "{}::{} ({})",
+                                            reg.name, info.name, info.access
+                                        ));
+                                        Type::default()
+                                    }),
+                                    reset_value: 0, // TODO parse info.default
+                                    access: Some(info.access.clone()),
+                                    source: Some(Source::Header {
+                                        file: name.to_string(),
+                                        line: i,
+                                    }),
+                                    ..Default::default()
+                                };
+                                state = State::FindDescription(pname.clone(), reg.clone(), bf);
+                            }
+                            None => {
+                                // skip the field, its description is
+                                // passed over while looking for the next
+                                header
+                                    .invalid_bit_fields
+                                    .push((info.name.clone(), info.bitpos.clone()));
+                                state = State::CheckEnd(pname.clone(), reg.clone());
+                            }
+                        },
+                        _ => {
+                            header.messages.push(format!(
+                                "Failed to match reg info at {}:{} ('{}')",
+                                name, i, text
+                            ));
+                            state = State::FindReg;
+                        }
+                    }
+                    break; // next line
+                }
+                State::FindDescription(ref mut pname, ref mut reg, ref mut bf) => {
+                    // only a line closing the comment can complete it
+                    buffer.push_str(text);
+                    buffer.push(' ');
+                    if !text.contains("*/") {
+                        break; // next line
+                    }
+                    if let Some(m) = re_reg_desc.captures(&buffer) {
+                        bf.description = m[1].split_whitespace().collect::<Vec<_>>().join(" ");
+                        buffer.clear();
+                        reg.bit_fields.push(bf.clone()); // add the bit field to the reg
+                        state = State::CheckEnd(pname.clone(), reg.clone());
+                    }
+                    break; // next line
+                }
+                State::CheckEnd(ref mut pname, ref mut reg) => match token {
+                    Token::Blank => {
+                        // we're done with this register
+                        header.registers.push((pname.clone(), reg.clone()));
+                        state = State::FindReg;
+                        break; // next line
+                    }
+                    Token::BitInfo(_) => {
+                        // we've found the next bit field in the reg
+                        state = State::FindBitFieldInfo(pname.clone(), reg.clone());
+                    }
+                    _ => break, // next line
+                },
+            }
+        }
+    }
+
+    // the directives closing the header are not part of the lines, a register
+    // still pending at the end of them is complete
+    if let State::CheckEnd(pname, reg) = state {
+        header.registers.push((pname, reg));
+    }
+
+    header.warnings = pp.warnings;
+    header
+}
 
-    let f = BufWriter::new(File::create(filename).unwrap());
-    svd.encode().unwrap().write(f).unwrap();
+pub fn create_device(chip: ChipType, pp: &Preprocessor, cache: &Cache) -> Device {
+    let mut device = parse_idf(&chip, pp, cache);
+    device.sort();
+    device.assign_widths();
+    add_write_constraints(&mut device);
+    device
 }
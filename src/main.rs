@@ -1,34 +1,338 @@
-use std::str::FromStr;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    process,
+    str::FromStr,
+};
 
-use clap::{app_from_crate, Arg};
-use common::ChipType;
+use clap::{app_from_crate, App, AppSettings, Arg, ArgMatches};
+use common::{
+    apply_group_overrides, apply_peripheral_metadata, build_c_headers, build_html,
+    build_linker_scripts, build_markdown, build_svd, file_to_string, init_logger,
+    load_group_overrides, load_model, load_peripheral_metadata, warnings, Cache, ChipType, Device,
+    Emit, Preprocessor,
+};
+use log::{error, info, LevelFilter};
+use xmltree::EmitterConfig;
 
 mod common;
 mod idf;
+mod pac;
 mod sdk;
 
+const PERIPHERAL_GROUPS: &'static str = "peripheral_groups.json";
+const PERIPHERAL_METADATA: &'static str = "peripheral_metadata.json";
+
+/// Arguments selecting the chip and how its headers are evaluated, shared by
+/// all commands
+fn input_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::with_name("CHIP")
+            .help("which device's SVD to generate")
+            .required_unless("all-chips")
+            .index(1)
+            .possible_values(&["ESP32", "ESP8266", "ESP32C3"])
+            .case_insensitive(true),
+        Arg::with_name("all-chips")
+            .help("generate for every supported chip, each to its default output")
+            .long("all-chips")
+            .conflicts_with_all(&["CHIP", "model", "output"]),
+        Arg::with_name("define")
+            .help("define a macro when evaluating the headers")
+            .short('D')
+            .long("define")
+            .value_name("NAME[=VALUE]")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("sdkconfig")
+            .help("sdkconfig with the configuration to evaluate the headers for")
+            .long("sdkconfig")
+            .value_name("FILE")
+            .takes_value(true),
+        Arg::with_name("model")
+            .help("read the model from a file written by --emit instead of parsing the headers")
+            .long("model")
+            .value_name("FILE")
+            .takes_value(true),
+        Arg::with_name("cache")
+            .help("keep parse results in this directory, to only parse changed headers next time")
+            .long("cache")
+            .value_name("DIR")
+            .takes_value(true),
+        Arg::with_name("verbose")
+            .help("log more details, given twice to also trace the parsers")
+            .short('v')
+            .long("verbose")
+            .multiple_occurrences(true),
+        Arg::with_name("quiet")
+            .help("only log errors")
+            .short('q')
+            .long("quiet")
+            .conflicts_with("verbose"),
+        Arg::with_name("log")
+            .help("log level per target, e.g. `sdk=debug,doc=off` (targets: idf, sdk, doc, model, build)")
+            .long("log")
+            .value_name("FILTER")
+            .takes_value(true),
+    ]
+}
+
 fn main() {
     let matches = app_from_crate!("\n")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .args(input_args())
         .arg(
-            Arg::with_name("CHIP")
-                .help("which device's SVD to generate")
-                .required(true)
-                .index(1)
-                .possible_values(&["ESP32", "ESP8266", "ESP32C3"])
-                .case_insensitive(true),
+            Arg::with_name("emit")
+                .help("what to generate from the parsed model")
+                .long("emit")
+                .takes_value(true)
+                .possible_values(&[
+                    "svd",
+                    "model-json",
+                    "model-yaml",
+                    "c-header",
+                    "html",
+                    "markdown",
+                    "linker",
+                ])
+                .default_value("svd"),
+        )
+        .arg(
+            Arg::with_name("structs")
+                .help("also write <periph>_struct.h with bitfield unions when generating C headers")
+                .long("structs"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .help("file or directory to write to, `-` for stdout [default: <chip>.<ext>]")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .subcommand(
+            App::new("pac")
+                .about("generate a peripheral access crate with svd2rust")
+                .args(input_args())
+                .arg(
+                    Arg::with_name("output")
+                        .help("directory of the crate [default: <chip>]")
+                        .short('o')
+                        .long("output")
+                        .value_name("DIR")
+                        .takes_value(true),
+                ),
         )
         .get_matches();
 
-    // Based on which chip has been selected, invoke the appropriate SVD
-    // builder (since the ESP32 and ESP8266 have different SDKs).
-    let chip = matches.value_of("CHIP").unwrap().to_uppercase();
-    let chip = ChipType::from_str(&chip);
-    match chip {
-        Ok(chip) => match chip {
-            ChipType::ESP32 => idf::create_svd(chip),
-            ChipType::ESP32C3 => idf::create_svd(chip),
-            ChipType::ESP8266 => sdk::create_svd(),
+    let (matches, pac) = match matches.subcommand_matches("pac") {
+        Some(matches) => (matches, true),
+        None => (&matches, false),
+    };
+
+    let level = match (
+        matches.is_present("quiet"),
+        matches.occurrences_of("verbose"),
+    ) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    init_logger(level, matches.value_of("log"));
+
+    // Conditionals in the headers are evaluated against these defines
+    let mut pp = Preprocessor::new();
+    if let Some(config) = matches.value_of("sdkconfig") {
+        pp.define_config(&file_to_string(config));
+    }
+    if let Some(defines) = matches.values_of("define") {
+        defines.for_each(|define| pp.define(define));
+    }
+
+    let chips = if matches.is_present("all-chips") {
+        ChipType::ALL.to_vec()
+    } else {
+        let chip = matches.value_of("CHIP").unwrap().to_uppercase();
+        match ChipType::from_str(&chip) {
+            Ok(chip) => vec![chip],
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+    };
+
+    // with `--all-chips` the remaining chips are still generated after a
+    // failure, the exit status reports it at the end
+    let cache = Cache::new(matches.value_of("cache"));
+    let mut failed = false;
+    for chip in chips {
+        let warned = warnings();
+        match generate(chip, matches, &pp, &cache, pac) {
+            Ok(device) => summary(&device, warnings() - warned),
+            Err(e) => {
+                error!("{}", e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// Logs the size of the generated model along with the number of warnings
+/// logged while generating it
+fn summary(device: &Device, warnings: usize) {
+    let registers = device.peripherals.values().map(|p| p.registers.len());
+    let fields = device
+        .peripherals
+        .values()
+        .flat_map(|p| p.registers.iter())
+        .map(|r| r.bit_fields.len());
+
+    info!(
+        "{}: {} peripherals, {} registers, {} fields, {} interrupts, {} warnings",
+        device.chip.to_string(),
+        device.peripherals.len(),
+        registers.sum::<usize>(),
+        fields.sum::<usize>(),
+        device.interrupts.len(),
+        warnings
+    );
+}
+
+fn generate(
+    chip: ChipType,
+    matches: &ArgMatches,
+    pp: &Preprocessor,
+    cache: &Cache,
+    pac: bool,
+) -> Result<Device, String> {
+    // Based on which chip has been selected, invoke the appropriate parser
+    // (since the ESP32 and ESP8266 have different SDKs), unless a previously
+    // dumped model is given.
+    let mut device = match matches.value_of("model") {
+        Some(model) => match load_model(model) {
+            Ok(device) if device.chip == chip => device,
+            Ok(device) => {
+                return Err(format!(
+                    "The model in {} is for {}, not {}",
+                    model,
+                    device.chip.to_string(),
+                    chip.to_string()
+                ))
+            }
+            Err(e) => return Err(format!("Failed to load {}: {}", model, e)),
         },
-        Err(e) => println!("{}", e),
+        None => match chip {
+            ChipType::ESP32 => idf::create_device(chip, pp, cache),
+            ChipType::ESP32C3 => idf::create_device(chip, pp, cache),
+            ChipType::ESP8266 => sdk::create_device(pp, cache),
+        },
+    };
+
+    let metadata = load_peripheral_metadata(PERIPHERAL_METADATA, chip);
+    apply_peripheral_metadata(&mut device, metadata);
+
+    let groups = load_group_overrides(PERIPHERAL_GROUPS, chip);
+    apply_group_overrides(&mut device, groups);
+
+    // the outputs only change along with the model and the options
+    let model = serde_json::to_string(&device).unwrap();
+
+    if pac {
+        let dir = match matches.value_of("output") {
+            Some(output) => output.to_owned(),
+            None => chip.to_string().to_lowercase(),
+        };
+        let stamp = Cache::key(&[&model, "pac"]);
+        if up_to_date(cache, &dir, &stamp) {
+            info!(target: "build", "{} is up to date", dir);
+            return Ok(device);
+        }
+
+        let svd = build_svd(&device).map_err(|_| "Failed to encode the SVD".to_string())?;
+        pac::create_pac(chip, &svd, Path::new(&dir))
+            .map_err(|e| format!("Failed to generate the PAC: {}", e))?;
+        cache.put("output", &Cache::key(&[&dir]), &stamp);
+        return Ok(device);
+    }
+
+    let emit = Emit::from_str(matches.value_of("emit").unwrap()).unwrap();
+    let filename = match matches.value_of("output") {
+        Some(output) => output.to_owned(),
+        None => emit.default_output(chip),
+    };
+
+    let structs = matches.is_present("structs");
+    let stamp = Cache::key(&[&model, &format!("{:?}", emit), &structs.to_string()]);
+    if up_to_date(cache, &filename, &stamp) {
+        info!(target: "build", "{} is up to date", filename);
+        return Ok(device);
+    }
+
+    // these are split over a file per peripheral
+    if emit.is_directory() {
+        if filename == "-" {
+            return Err(format!("{:?} can not be written to stdout", emit));
+        }
+        let dir = Path::new(&filename);
+        match emit {
+            Emit::CHeader => build_c_headers(&device, dir, structs),
+            Emit::Html => build_html(&device, dir),
+            Emit::Markdown => build_markdown(&device, dir),
+            Emit::Linker => build_linker_scripts(&device, dir),
+            _ => unreachable!(),
+        }
+        .map_err(|e| format!("Failed to write {}: {}", filename, e))?;
+    } else {
+        let out: Box<dyn Write> = if filename == "-" {
+            Box::new(io::stdout())
+        } else {
+            let file = File::create(&filename)
+                .map_err(|e| format!("Failed to create {}: {}", filename, e))?;
+            Box::new(file)
+        };
+
+        write_output(&device, emit, BufWriter::new(out))
+            .map_err(|e| format!("Failed to write {}: {}", filename, e))?;
+    }
+
+    if filename != "-" {
+        cache.put("output", &Cache::key(&[&filename]), &stamp);
+    }
+
+    Ok(device)
+}
+
+/// Whether `output` was generated by an earlier run from the same model and
+/// options, identified by `stamp`
+fn up_to_date(cache: &Cache, output: &str, stamp: &str) -> bool {
+    output != "-"
+        && Path::new(output).exists()
+        && cache
+            .get::<String>("output", &Cache::key(&[output]))
+            .as_deref()
+            == Some(stamp)
+}
+
+fn write_output<W: Write>(device: &Device, emit: Emit, mut out: W) -> Result<(), String> {
+    match emit {
+        // indented like the SVDs written by svd-parser itself
+        Emit::Svd => build_svd(device)
+            .map_err(|_| "the SVD could not be encoded".to_string())?
+            .write_with_config(&mut out, EmitterConfig::new().perform_indent(true))
+            .map_err(|e| e.to_string())?,
+        Emit::ModelJson => {
+            serde_json::to_writer_pretty(&mut out, device).map_err(|e| e.to_string())?
+        }
+        Emit::ModelYaml => serde_yaml::to_writer(&mut out, device).map_err(|e| e.to_string())?,
+        _ => unreachable!(),
     }
+    // dropping the writer would ignore errors flushing it
+    out.flush().map_err(|e| e.to_string())
 }
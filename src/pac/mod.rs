@@ -0,0 +1,120 @@
+use std::{
+    fs::{self, File},
+    io::prelude::*,
+    path::Path,
+    process::Command,
+};
+
+use log::warn;
+use svd2rust::{Config, Target};
+use xmltree::Element;
+
+use crate::common::ChipType;
+
+const CARGO_TOML: &'static str = r#"[package]
+name = "{name}"
+description = "Peripheral access crate for the {chip}"
+version = "0.1.0"
+edition = "2018"
+categories = ["embedded", "hardware-support", "no-std"]
+keywords = ["{name}", "pac", "svd2rust"]
+license = "MIT OR Apache-2.0"
+
+[dependencies]
+bare-metal = "0.2"
+vcell = "0.1"
+{dependencies}
+
+[features]
+rt = ["{rt}"]
+"#;
+
+fn target(chip: ChipType) -> Target {
+    match chip {
+        ChipType::ESP32 | ChipType::ESP8266 => Target::XtensaLX,
+        ChipType::ESP32C3 => Target::RISCV,
+    }
+}
+
+/// Dependencies of the generated code, and the runtime crate enabled by the
+/// `rt` feature
+fn dependencies(chip: ChipType) -> (&'static str, &'static str) {
+    match chip {
+        ChipType::ESP32 => (
+            "xtensa-lx = { version = \"0.4\", features = [\"lx6\"] }\n\
+             xtensa-lx-rt = { version = \"0.7\", features = [\"lx6\"], optional = true }",
+            "xtensa-lx-rt",
+        ),
+        ChipType::ESP8266 => (
+            "xtensa-lx = { version = \"0.4\", features = [\"lx106\"] }\n\
+             xtensa-lx-rt = { version = \"0.7\", features = [\"lx106\"], optional = true }",
+            "xtensa-lx-rt",
+        ),
+        ChipType::ESP32C3 => (
+            "riscv = \"0.6\"\n\
+             riscv-rt = { version = \"0.8\", optional = true }",
+            "riscv-rt",
+        ),
+    }
+}
+
+/// Generates a peripheral access crate for the device with svd2rust, the
+/// crate manifest is only written when there is none yet so that it can be
+/// maintained by hand, everything else is regenerated
+pub fn create_pac(chip: ChipType, svd: &Element, dir: &Path) -> Result<(), String> {
+    let mut xml = vec![];
+    svd.write(&mut xml).map_err(|e| e.to_string())?;
+    let xml = String::from_utf8(xml).map_err(|e| e.to_string())?;
+
+    let config = Config {
+        target: target(chip),
+        ..Config::default()
+    };
+    let generation = svd2rust::generate(&xml, &config).map_err(|e| e.to_string())?;
+
+    let src = dir.join("src");
+    if src.exists() {
+        fs::remove_dir_all(&src).map_err(|e| e.to_string())?;
+    }
+    fs::create_dir_all(&src).map_err(|e| e.to_string())?;
+
+    let manifest = dir.join("Cargo.toml");
+    if !manifest.exists() {
+        let name = chip.to_string().to_lowercase();
+        let (dependencies, rt) = dependencies(chip);
+        let cargo_toml = CARGO_TOML
+            .replace("{name}", &name)
+            .replace("{chip}", &chip.to_string())
+            .replace("{dependencies}", dependencies)
+            .replace("{rt}", rt);
+        write_file(&manifest, &cargo_toml)?;
+    }
+
+    if let Some(device_specific) = &generation.device_specific {
+        write_file(&dir.join("device.x"), &device_specific.device_x)?;
+        write_file(&dir.join("build.rs"), &device_specific.build_rs)?;
+    }
+
+    // split the generated code into a module per peripheral
+    form::create_directory_structure(&src, generation.lib_rs, false).map_err(|e| e.to_string())?;
+
+    let formatted = Command::new("rustfmt")
+        .arg("--edition")
+        .arg("2018")
+        .arg(src.join("lib.rs"))
+        .status();
+    match formatted {
+        Ok(status) if status.success() => {}
+        _ => warn!(
+            target: "build",
+            "Could not run rustfmt, the generated code is left unformatted"),
+    }
+
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), String> {
+    File::create(path)
+        .and_then(|mut f| f.write_all(contents.as_bytes()))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
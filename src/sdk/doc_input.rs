@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -11,32 +13,112 @@ struct Column {
 }
 
 #[derive(Debug, Deserialize)]
-#[serde(from = "Vec<JSONInput>")]
+#[serde(try_from = "Vec<JSONInput>")]
 pub struct Table {
     pub header: Vec<String>,
     pub data: Vec<Vec<String>>,
 }
 
-impl From<Vec<JSONInput>> for Table {
-    fn from(mut input: Vec<JSONInput>) -> Self {
-        let input = input.pop().unwrap();
-        let mut data = input.data.into_iter().skip(1); // first row is garbage
-
-        let header = loop {
-            let header = data.next().unwrap();
-            if !header[4].text.is_empty() {
-                break header
-                    .into_iter()
-                    .map(|c| c.text.replace("\r", ""))
-                    .collect();
+fn cells(row: Vec<Column>) -> Vec<String> {
+    row.into_iter().map(|c| c.text.replace("\r", "")).collect()
+}
+
+impl TryFrom<Vec<JSONInput>> for Table {
+    type Error = String;
+
+    fn try_from(input: Vec<JSONInput>) -> Result<Self, String> {
+        let mut title = vec![];
+        let mut header = vec![];
+        let mut data = vec![];
+
+        // tables spanning multiple pages are extracted as one input per page,
+        // the first one starts with the title of the table followed by the
+        // header, which the following pages may or may not repeat
+        for page in input {
+            for row in page.data.into_iter().map(cells) {
+                if header.is_empty() {
+                    // the header is the first row with a default column
+                    if matches!(row.get(4), Some(cell) if !cell.is_empty()) {
+                        header = row;
+                    } else {
+                        title.push(row);
+                    }
+                } else if row != header && !title.contains(&row) {
+                    data.push(row);
+                }
             }
-        };
+        }
+
+        if header.is_empty() {
+            return Err("no header row found".to_string());
+        }
+
+        Ok(Table { header, data })
+    }
+}
 
-        Table {
-            header,
-            data: data
-                .map(|row| row.into_iter().map(|c| c.text).collect())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(rows: &[&[&str]]) -> JSONInput {
+        JSONInput {
+            data: rows
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|text| Column {
+                            text: text.to_string(),
+                        })
+                        .collect()
+                })
                 .collect(),
         }
     }
+
+    const TITLE: &[&str] = &["Register", "", "", "", "", "", ""];
+    const HEADER: &[&str] = &[
+        "Address",
+        "Name",
+        "Signal",
+        "BitPos",
+        "Default",
+        "SW(R/W)",
+        "Description",
+    ];
+    const FIRST: &[&str] = &["0x0", "FRC1_LOAD", "", "", "", "", ""];
+    const SECOND: &[&str] = &[
+        "0x4",
+        "FRC1_COUNT",
+        "frc1_count",
+        "[22:0]",
+        "23'h7fffff",
+        "RO",
+        "",
+    ];
+
+    #[test]
+    fn repeated_headers_are_skipped() {
+        let table = Table::try_from(vec![
+            page(&[TITLE, HEADER, FIRST]),
+            page(&[TITLE, HEADER, SECOND]),
+        ])
+        .unwrap();
+
+        assert_eq!(table.header, HEADER);
+        assert_eq!(table.data, vec![FIRST, SECOND]);
+    }
+
+    #[test]
+    fn pages_without_a_header_keep_their_first_row() {
+        let table =
+            Table::try_from(vec![page(&[TITLE, HEADER, FIRST]), page(&[SECOND, FIRST])]).unwrap();
+
+        assert_eq!(table.data, vec![FIRST, SECOND, FIRST]);
+    }
+
+    #[test]
+    fn tables_without_a_header_are_rejected() {
+        assert!(Table::try_from(vec![page(&[TITLE, &["0x0"]])]).is_err());
+    }
 }
@@ -0,0 +1,78 @@
+use std::fs::read_to_string;
+
+use serde::{de::Error, Deserialize, Deserializer};
+
+/// A register table extracted from the technical reference by `make`
+#[derive(Debug, Deserialize)]
+pub struct DocTable {
+    /// Name of the extracted table, the JSON is read from `build/<table>.json`
+    pub table: String,
+    /// Page range within the technical reference, as passed to tabula
+    pub pages: String,
+    /// Name of the peripheral the table describes
+    pub peripheral: String,
+    /// Base address of the first instance, falls back to the address found
+    /// in the headers when not set
+    #[serde(default, deserialize_with = "deserialize_address")]
+    pub base_address: Option<u32>,
+    /// Number of identical instances of the peripheral
+    #[serde(default = "default_instances")]
+    pub instances: u32,
+    /// Offset between the base addresses of two consecutive instances
+    #[serde(default, deserialize_with = "deserialize_stride")]
+    pub stride: i64,
+}
+
+impl DocTable {
+    pub fn path(&self) -> String {
+        format!("build/{}.json", self.table)
+    }
+
+    /// Names and base addresses of all instances described by this table
+    pub fn instances(&self) -> Vec<(String, Option<u32>)> {
+        if self.instances <= 1 {
+            return vec![(self.peripheral.clone(), self.base_address)];
+        }
+
+        (0..self.instances)
+            .map(|i| {
+                let address = self
+                    .base_address
+                    .map(|base| (i64::from(base) + i64::from(i) * self.stride) as u32);
+                (format!("{}{}", self.peripheral, i), address)
+            })
+            .collect()
+    }
+}
+
+fn default_instances() -> u32 {
+    1
+}
+
+fn parse_hex(value: &str) -> Option<i64> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+    let value = i64::from_str_radix(value.trim_start_matches("0x"), 16).ok()?;
+
+    Some(if negative { -value } else { value })
+}
+
+fn deserialize_address<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u32>, D::Error> {
+    let value = String::deserialize(d)?;
+    match parse_hex(&value) {
+        Some(address) if (0..=i64::from(u32::MAX)).contains(&address) => Ok(Some(address as u32)),
+        _ => Err(D::Error::custom(format!("invalid address: {}", value))),
+    }
+}
+
+fn deserialize_stride<'de, D: Deserializer<'de>>(d: D) -> Result<i64, D::Error> {
+    let value = String::deserialize(d)?;
+    parse_hex(&value).ok_or_else(|| D::Error::custom(format!("invalid stride: {}", value)))
+}
+
+pub fn load_manifest(name: &str) -> Result<Vec<DocTable>, String> {
+    let file = read_to_string(name).map_err(|e| format!("{}: {}", name, e))?;
+    serde_json::from_str(&file).map_err(|e| format!("{}: {}", name, e))
+}
@@ -1,8 +1,82 @@
+use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::str::FromStr;
 
+use log::warn;
+use serde::Deserialize;
+
 use crate::sdk::doc_input::Table;
-use crate::sdk::{BitField, Bits, Peripheral, Register, Type};
+use crate::sdk::{BitField, Bits, Peripheral, Register, Source, Type};
+
+/// Corrections for rows which are broken in the technical reference, the
+/// values are given in the same format as they appear in the tables
+#[derive(Debug, Default, Deserialize)]
+pub struct RowOverride {
+    /// Register the row belongs to
+    pub register: String,
+    /// Signal of the row, when not set the override applies to the row
+    /// starting the register
+    #[serde(default)]
+    pub signal: Option<String>,
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub bit_pos: Option<String>,
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+/// Row overrides, keyed by table name
+pub type Overrides = HashMap<String, Vec<RowOverride>>;
+
+/// Cells of a row as extracted from the table, before decoding
+#[derive(Debug, Default)]
+struct RawRow {
+    address: String,
+    reg_name: String,
+    signal: String,
+    bit_pos: String,
+    default: String,
+    sw: String,
+    description: String,
+}
+
+impl RawRow {
+    /// The row holds the remaining cells of a row naming a field without its
+    /// bit position, a row naming another field never continues a row
+    fn is_continuation_of(&self, other: &RawRow) -> bool {
+        self.address.is_empty()
+            && self.reg_name.is_empty()
+            && self.signal.is_empty()
+            && !self.bit_pos.is_empty()
+            && !other.signal.is_empty()
+            && other.bit_pos.is_empty()
+    }
+
+    fn merge(&mut self, other: RawRow) {
+        self.bit_pos.push_str(&other.bit_pos);
+        self.default.push_str(&other.default);
+        self.sw.push_str(&other.sw);
+        if !other.description.is_empty() {
+            if !self.description.is_empty() {
+                self.description.push(' ');
+            }
+            self.description.push_str(&other.description);
+        }
+    }
+
+    fn apply(&mut self, row_override: &RowOverride) {
+        if let Some(address) = &row_override.address {
+            self.address = address.clone();
+        }
+        if let Some(bit_pos) = &row_override.bit_pos {
+            self.bit_pos = bit_pos.clone();
+        }
+        if let Some(default) = &row_override.default {
+            self.default = default.clone();
+        }
+    }
+}
 
 struct Row {
     address: Option<u32>,
@@ -10,31 +84,53 @@ struct Row {
     signal: String,
     bit_pos: Option<Bits>,
     default: Option<u32>,
+    sw: String,
     ty: Option<Type>,
     description: String,
 }
 
-fn decode_table(input: Table) -> Peripheral {
-    let gpio_mode = input.header[0] == "NUM";
+fn decode_table(name: &str, input: Table, overrides: &[RowOverride]) -> Peripheral {
+    let gpio_mode = input.header.first().map(String::as_str) == Some("NUM");
     let mut peripheral = Peripheral::default();
+    let mut invalid_rows = vec![];
 
     let mut reg = Register::default();
+    let mut skip_register = false;
     let mut last_type = Type::ReadWrite;
-    for line in input.data {
-        if line[0].contains('~') {
-            continue;
-        }
+    for (i, row) in repair_rows(name, input.data, gpio_mode, overrides, &mut invalid_rows) {
+        let row = match decode_row(row, gpio_mode) {
+            Ok(row) => row,
+            Err(RowError::Address(e)) => {
+                // don't attribute the fields of the broken register to the
+                // previous one
+                if !reg.name.is_empty() {
+                    peripheral.registers.push(reg.clone());
+                }
+                reg = Register::default();
+                skip_register = true;
+                invalid_rows.push(format!("{}:{} {}", name, i, e));
+                continue;
+            }
+            Err(RowError::Field(e)) => {
+                invalid_rows.push(format!("{}:{} {}", name, i, e));
+                continue;
+            }
+        };
 
-        let row = extract_row(line, gpio_mode);
         if row.address.is_some() {
             // start of new register, push the old one
             if !reg.name.is_empty() {
                 peripheral.registers.push(reg.clone());
             }
 
+            skip_register = false;
             reg = Register::default();
             reg.width = 32;
             reg.address = row.address.unwrap();
+            reg.source = Some(Source::Doc {
+                table: name.to_string(),
+                row: i,
+            });
             reg.name = row.reg_name.trim_end_matches("_ADDRESS").to_string();
             reg.description = if row.description.is_empty() {
                 reg.name.clone()
@@ -43,6 +139,15 @@ fn decode_table(input: Table) -> Peripheral {
             };
         }
 
+        if skip_register {
+            continue;
+        }
+
+        if !row.signal.is_empty() && row.bit_pos.is_none() {
+            invalid_rows.push(format!("{}:{} no bit position for {}", name, i, row.signal));
+            continue;
+        }
+
         if !row.signal.is_empty()
             && row.bit_pos.is_some()
             && (row.ty.is_some() || !row.signal.is_empty())
@@ -59,6 +164,16 @@ fn decode_table(input: Table) -> Peripheral {
                 description: row.description,
                 reset_value: row.default.unwrap_or_default(),
                 type_: row.ty.unwrap_or(last_type),
+                access: if row.sw.is_empty() {
+                    None
+                } else {
+                    Some(row.sw)
+                },
+                source: Some(Source::Doc {
+                    table: name.to_string(),
+                    row: i,
+                }),
+                write_constraint: None,
             };
 
             last_type = bit_field.type_;
@@ -71,100 +186,264 @@ fn decode_table(input: Table) -> Peripheral {
         }
     }
 
-    peripheral.registers.push(reg.clone());
+    if !reg.name.is_empty() {
+        peripheral.registers.push(reg);
+    }
+
+    if !invalid_rows.is_empty() {
+        warn!(target: "doc", "The following rows failed to parse {:?}", invalid_rows);
+    }
 
     peripheral
 }
 
-fn extract_row(line: Vec<String>, gpio_mode: bool) -> Row {
-    if gpio_mode {
-        let mut parts = line.into_iter().skip(1).map(|part| part.replace('\r', ""));
-        let address = parts.next().unwrap();
-        let _ = parts.next();
-        let reg_name = parts.next().unwrap();
-        let signal = parts.next().unwrap();
-        let bit_pos = parts.next().unwrap();
-        let sw = parts.next().unwrap();
-        let description = parts.next().unwrap();
-
-        Row {
-            address: parse_addr(&address).map(|addr| addr * 4),
-            reg_name,
-            signal,
-            bit_pos: parse_bits(&bit_pos),
-            default: None,
-            ty: Type::from_str(&sw).ok(),
-            description,
+/// Extracts the cells of every row, applies the overrides for the table and
+/// joins fields which have been split over multiple lines
+fn repair_rows(
+    name: &str,
+    data: Vec<Vec<String>>,
+    gpio_mode: bool,
+    overrides: &[RowOverride],
+    invalid_rows: &mut Vec<String>,
+) -> Vec<(usize, RawRow)> {
+    let mut rows: Vec<(usize, RawRow)> = vec![];
+    let mut current_reg = String::new();
+
+    for (i, line) in data.into_iter().enumerate() {
+        if line.first().map_or(true, |cell| cell.contains('~')) {
+            continue;
         }
-    } else {
-        let mut parts = line.into_iter().map(|part| part.replace('\r', ""));
-        let mut address = parts.next().unwrap();
-        let reg_name = parts.next().unwrap();
-        let signal = parts.next().unwrap();
-        let bit_pos = parts.next().unwrap();
-        let default = parts.next().unwrap();
-        let sw = parts.next().unwrap();
-        let description = parts.next().unwrap();
-
-        // broken row in the table
-        if reg_name == "UART_STATUS" {
-            address = "0x1c".to_string();
+
+        let mut row = match extract_row(line, gpio_mode) {
+            Some(row) => row,
+            None => {
+                invalid_rows.push(format!("{}:{} missing columns", name, i));
+                continue;
+            }
+        };
+
+        if !row.reg_name.is_empty() {
+            current_reg = row.reg_name.clone();
         }
 
-        Row {
-            address: parse_addr(&address),
-            reg_name,
-            signal,
-            bit_pos: parse_bits(&bit_pos),
-            default: parse_default(&default),
-            ty: Type::from_str(&sw).ok(),
-            description,
+        for row_override in overrides.iter().filter(|o| o.register == current_reg) {
+            let matches = match &row_override.signal {
+                Some(signal) => *signal == row.signal,
+                None => !row.reg_name.is_empty(),
+            };
+            if matches {
+                row.apply(row_override);
+            }
         }
+
+        match rows.last_mut() {
+            Some((_, last)) if row.is_continuation_of(last) => last.merge(row),
+            _ => rows.push((i, row)),
+        }
+    }
+
+    rows
+}
+
+fn extract_row(line: Vec<String>, gpio_mode: bool) -> Option<RawRow> {
+    let mut parts = line.into_iter().map(|part| part.replace('\r', ""));
+
+    if gpio_mode {
+        let _num = parts.next()?;
+        let address = parts.next()?;
+        let _ = parts.next()?;
+
+        Some(RawRow {
+            address,
+            reg_name: parts.next()?,
+            signal: parts.next()?,
+            bit_pos: parts.next()?,
+            default: String::new(),
+            sw: parts.next()?,
+            description: parts.next()?,
+        })
+    } else {
+        Some(RawRow {
+            address: parts.next()?,
+            reg_name: parts.next()?,
+            signal: parts.next()?,
+            bit_pos: parts.next()?,
+            default: parts.next()?,
+            sw: parts.next()?,
+            description: parts.next()?,
+        })
     }
 }
 
-fn parse_addr(addr: &str) -> Option<u32> {
+enum RowError {
+    Address(String),
+    Field(String),
+}
+
+fn decode_row(row: RawRow, gpio_mode: bool) -> Result<Row, RowError> {
+    let address = parse_addr(&row.address).map_err(RowError::Address)?;
+
+    Ok(Row {
+        // the gpio table lists word offsets
+        address: if gpio_mode {
+            address.map(|addr| addr * 4)
+        } else {
+            address
+        },
+        reg_name: row.reg_name,
+        signal: row.signal,
+        bit_pos: parse_bits(&row.bit_pos).map_err(RowError::Field)?,
+        default: parse_default(&row.default).map_err(RowError::Field)?,
+        ty: Type::from_str(&row.sw).ok(),
+        sw: row.sw,
+        description: row.description,
+    })
+}
+
+fn parse_addr(addr: &str) -> Result<Option<u32>, String> {
     if addr.is_empty() || addr.contains('~') {
-        return None;
+        return Ok(None);
     }
-    Some(u32::from_str_radix(&addr.trim_start_matches("0x"), 16).unwrap())
+
+    u32::from_str_radix(addr.trim_start_matches("0x"), 16)
+        .map(Some)
+        .map_err(|_| format!("invalid address '{}'", addr))
 }
 
-fn parse_bits(bit_pos: &str) -> Option<Bits> {
+fn parse_bits(bit_pos: &str) -> Result<Option<Bits>, String> {
     if bit_pos.is_empty() {
-        return None;
+        return Ok(None);
     }
 
     let nums = bit_pos.trim_start_matches('[').trim_end_matches(']');
-    let parts: Vec<u8> = nums
+    let parts = nums
         .split(':')
-        .map(|digits| u8::from_str(digits).unwrap())
-        .collect();
+        .map(|digits| u8::from_str(digits.trim()))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| format!("invalid bit position '{}'", bit_pos))?;
 
-    Some(if parts.len() == 1 {
-        Bits::Single(parts[0])
-    } else {
-        Bits::Range(parts[1]..=parts[0])
-    })
+    Ok(Some(match parts[..] {
+        [bit] => Bits::Single(bit),
+        [high, low] if high >= low => Bits::Range(low..=high),
+        _ => return Err(format!("invalid bit position '{}'", bit_pos)),
+    }))
 }
 
-fn parse_default(default: &str) -> Option<u32> {
+fn parse_default(default: &str) -> Result<Option<u32>, String> {
     if default.is_empty() {
-        return None;
+        return Ok(None);
     }
 
-    let default = default.split('\'').skip(1).next().unwrap().replace('_', "");
-    Some(match &default[..1] {
-        "b" => u32::from_str_radix(&default[1..], 2).unwrap(),
-        "d" => u32::from_str_radix(&default[1..], 10).unwrap(),
-        "h" => u32::from_str_radix(&default[1..], 16).unwrap(),
-        _ => panic!("invalid default format"),
-    })
+    let invalid = || format!("invalid default '{}'", default);
+    let value = default
+        .split('\'')
+        .nth(1)
+        .ok_or_else(invalid)?
+        .replace('_', "");
+    let radix = match value.get(..1) {
+        Some("b") => 2,
+        Some("d") => 10,
+        Some("h") => 16,
+        _ => return Err(invalid()),
+    };
+
+    u32::from_str_radix(&value[1..], radix)
+        .map(Some)
+        .map_err(|_| invalid())
+}
+
+pub fn load_overrides(name: &str) -> Result<Overrides, String> {
+    let file = read_to_string(name).map_err(|e| format!("{}: {}", name, e))?;
+    serde_json::from_str(&file).map_err(|e| format!("{}: {}", name, e))
 }
 
-pub fn parse_doc(name: &str) -> Peripheral {
-    let file = read_to_string(name).unwrap();
-    let input = serde_json::from_str(&file).unwrap();
+pub fn parse_doc(name: &str, path: &str, overrides: &Overrides) -> Result<Peripheral, String> {
+    let file = read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let input = serde_json::from_str(&file).map_err(|e| format!("{}: {}", path, e))?;
+
+    let overrides = overrides.get(name).map_or(&[][..], |o| &o[..]);
+    Ok(decode_table(name, input, overrides))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(cells: [&str; 7]) -> Vec<String> {
+        cells.iter().map(|cell| cell.to_string()).collect()
+    }
+
+    fn repair(data: Vec<Vec<String>>) -> (Vec<(usize, RawRow)>, Vec<String>) {
+        let mut invalid_rows = vec![];
+        let rows = repair_rows("uart", data, false, &[], &mut invalid_rows);
+        (rows, invalid_rows)
+    }
+
+    #[test]
+    fn split_bit_positions_are_merged() {
+        let (rows, invalid_rows) = repair(vec![
+            line(["0x0", "UART_CONF", "", "", "", "", ""]),
+            line(["", "", "UART_RXFIFO_RST", "", "", "", ""]),
+            line(["", "", "", "[17]", "1'b0", "R/W", ""]),
+            line(["", "", "UART_TXFIFO_RST", "[18]", "1'b0", "R/W", ""]),
+        ]);
+
+        assert!(invalid_rows.is_empty());
+        assert_eq!(rows.len(), 3);
+        let (i, row) = &rows[1];
+        assert_eq!(*i, 1);
+        assert_eq!(row.signal, "UART_RXFIFO_RST");
+        assert_eq!(row.bit_pos, "[17]");
+        assert_eq!(row.default, "1'b0");
+        assert_eq!(row.sw, "R/W");
+        assert_eq!(rows[2].1.signal, "UART_TXFIFO_RST");
+    }
+
+    #[test]
+    fn wrapped_descriptions_are_joined() {
+        let (rows, _) = repair(vec![
+            line([
+                "",
+                "",
+                "UART_RXFIFO_FULL_THRHD",
+                "",
+                "",
+                "",
+                "the threshold of",
+            ]),
+            line([
+                "",
+                "",
+                "",
+                "[6:0]",
+                "7'h60",
+                "R/W",
+                "the rx fifo full interrupt",
+            ]),
+            line(["", "", "", "", "", "", "in bytes"]),
+        ]);
+
+        // rows only holding text are appended to the field when decoding
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0].1.description,
+            "the threshold of the rx fifo full interrupt"
+        );
+        assert_eq!(rows[1].1.description, "in bytes");
+    }
 
-    decode_table(input)
+    #[test]
+    fn rows_naming_fields_are_not_merged() {
+        let (rows, _) = repair(vec![
+            line(["", "", "UART_RXFIFO_RST", "", "", "", ""]),
+            line(["", "", "UART_TXFIFO_RST", "", "", "", ""]),
+            line(["", "", "", "[18]", "1'b0", "R/W", ""]),
+        ]);
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].1.signal, "UART_RXFIFO_RST");
+        assert_eq!(rows[0].1.bit_pos, "");
+        assert_eq!(rows[1].1.signal, "UART_TXFIFO_RST");
+        assert_eq!(rows[1].1.bit_pos, "[18]");
+    }
 }
@@ -0,0 +1,294 @@
+use std::str::FromStr;
+
+use regex::Regex;
+
+use crate::common::{BaseAddr, Mask, MaskKind, RegDef, RegDefKind, Shift, Token, Type};
+
+// Regexes to find all the peripheral addresses
+const REG_BASE: &'static str =
+    r"\#define[\s*]+((?:DR_REG|REG|PERIPHS)_([0-9A-Za-z_]+)_BASE(?:_?A?DDR)?)(\(i\))?[\s*]+[^\s]";
+const REG_DEF: &'static str = r"\#define[\s*]+(?:PERIPHS_)?([^\s*]+)_(?:REG|ADDRESS|U|ADDR)[\s*]+\((?:DR_REG|REG|PERIPHS)_(.*)_BASE(?:_?A?DDR)? \+ (.*)\)";
+const REG_DEF_OFFSET: &'static str =
+    r"\#define[\s*]+(?:PERIPHS_)?([^\s*]+)_(?:ADDRESS|U|ADDR)[\s*]+(?:0x)?([0-9a-fA-F]+)";
+const REG_DEF_INDEX: &'static str = r"\#define[\s*]+(?:PERIPHS_)?([^\s*]+)_(?:REG|ADDRESS|U|ADDR)\(i\)[\s*]+\((?:DR_REG|REG|PERIPHS)_([0-9A-Za-z_]+)_BASE(?:_?A?DDR)?[\s*]*\(i\) \+ (.*?)\)";
+const REG_DEFINE_MASK: &'static str = r"\#define[\s*]+(?:PERIPHS_)?([^\s*]+)[\s*]+(\(*(?:0x[0-9a-fA-F]+|[0-9]+|BIT\(?[0-9]+\)?)[0-9a-fA-FxXuUlL\s()<>|&+*~-]*)";
+const REG_DEFINE_COMPANION: &'static str =
+    r"\#define[\s*]+(?:PERIPHS_)?([^\s*]+)_(S|s|M|V)[\s*]+([^\s].*)";
+const DEFINE_COMMENT: &'static str = r"(?://|/\*)(.*?)(?:\*/)?$";
+const SINGLE_BIT: &'static str = r"BIT\(?([0-9]+)\)?";
+
+/// Recognises the lines of the ESP8266_RTOS_SDK headers
+pub struct Lexer {
+    re_base: Regex,
+    re_reg: Regex,
+    re_reg_index: Regex,
+    re_reg_offset: Regex,
+    re_define: Regex,
+    re_companion: Regex,
+    re_comment: Regex,
+    re_single_bit: Regex,
+}
+
+impl Lexer {
+    pub fn new() -> Self {
+        Lexer {
+            re_base: Regex::new(REG_BASE).unwrap(),
+            re_reg: Regex::new(REG_DEF).unwrap(),
+            re_reg_index: Regex::new(REG_DEF_INDEX).unwrap(),
+            re_reg_offset: Regex::new(REG_DEF_OFFSET).unwrap(),
+            re_define: Regex::new(REG_DEFINE_MASK).unwrap(),
+            re_companion: Regex::new(REG_DEFINE_COMPANION).unwrap(),
+            re_comment: Regex::new(DEFINE_COMMENT).unwrap(),
+            re_single_bit: Regex::new(SINGLE_BIT).unwrap(),
+        }
+    }
+
+    pub fn token(&self, line: &str) -> Token {
+        if line.trim().is_empty() {
+            Token::Blank
+        } else if let Some(reg) = self.reg_def(line) {
+            Token::RegDef(reg)
+        } else if let Some(token) = self.companion(line) {
+            token
+        } else if let Some(mask) = self.mask(line) {
+            Token::Mask(mask)
+        } else {
+            Token::Other
+        }
+    }
+
+    pub fn reg_def(&self, line: &str) -> Option<RegDef> {
+        if let Some(m) = self.re_reg.captures(line) {
+            return Some(RegDef {
+                name: m[1].to_string(),
+                peripheral: m[2].to_string(),
+                offset: m[3].to_string(),
+                kind: RegDefKind::Base,
+            });
+        }
+        if let Some(m) = self.re_reg_index.captures(line) {
+            return Some(RegDef {
+                name: m[1].to_string(),
+                peripheral: m[2].to_string(),
+                offset: m[3].to_string(),
+                kind: RegDefKind::Indexed,
+            });
+        }
+
+        // the peripheral is named by the prefix of the register
+        let m = self.re_reg_offset.captures(line)?;
+        Some(RegDef {
+            name: m[1].to_string(),
+            peripheral: m[1].split('_').next().unwrap().to_string(),
+            offset: format!("0x{}", &m[2]),
+            kind: RegDefKind::Offset,
+        })
+    }
+
+    /// `FOO_S`, `FOO_M` or `FOO_V`
+    fn companion(&self, line: &str) -> Option<Token> {
+        let m = self.re_companion.captures(line)?;
+        let name = m[1].to_string();
+        let value = m[3].to_string();
+        let access = self.comment_access(line);
+
+        Some(match &m[2] {
+            "S" | "s" => Token::Shift(Shift {
+                name,
+                value,
+                access,
+            }),
+            suffix => Token::Mask(Mask {
+                name,
+                value,
+                kind: match suffix {
+                    "M" => MaskKind::Shifted,
+                    _ => MaskKind::Value,
+                },
+                single_bit: false,
+                access,
+            }),
+        })
+    }
+
+    /// `FOO` holding the mask in place, or a single `BIT(n)`
+    fn mask(&self, line: &str) -> Option<Mask> {
+        let m = self.re_define.captures(line)?;
+        let value = m[2].trim();
+
+        Some(Mask {
+            name: m[1].to_string(),
+            value: value.to_string(),
+            kind: MaskKind::Field,
+            single_bit: self.re_single_bit.is_match(value),
+            access: self.comment_access(line),
+        })
+    }
+
+    /// Picks up an access type, e.g. `RO` or `R/W`, from the comment on a line
+    fn comment_access(&self, line: &str) -> Option<Type> {
+        let comment = self.re_comment.captures(line)?;
+
+        comment[1]
+            .split(|c: char| c.is_whitespace() || c == ',' || c == ';' || c == '(' || c == ')')
+            .find_map(|word| Type::from_str(word).ok())
+    }
+
+    pub fn base_addr(&self, line: &str) -> Option<BaseAddr> {
+        let m = self.re_base.captures(line)?;
+
+        Some(BaseAddr {
+            define: m[1].to_string(),
+            peripheral: m[2].to_string(),
+            indexed: m.get(3).is_some(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::common::Token;
+
+    // names which don't end in the suffix of a companion define
+    const NAME: &str = "[A-Z][A-Z0-9]{0,8}(_[A-Z0-9]{2,8}){0,3}";
+
+    proptest! {
+        #[test]
+        fn reg_def_round_trips(name in NAME, peripheral in "[A-Z][A-Z0-9]{0,8}", offset in 0u32..0x1000) {
+            let line = format!(
+                "#define {}_REG                (REG_{}_BASE + 0x{:x})",
+                name, peripheral, offset
+            );
+
+            prop_assert_eq!(
+                Lexer::new().token(&line),
+                Token::RegDef(RegDef {
+                    name,
+                    peripheral,
+                    offset: format!("0x{:x}", offset),
+                    kind: RegDefKind::Base,
+                })
+            );
+        }
+
+        #[test]
+        fn indexed_reg_def_round_trips(name in NAME, peripheral in "[A-Z][A-Z0-9]{0,8}", offset in 0u32..0x1000) {
+            let line = format!(
+                "#define {}_REG(i)             (REG_{}_BASE(i) + 0x{:x})",
+                name, peripheral, offset
+            );
+
+            prop_assert_eq!(
+                Lexer::new().token(&line),
+                Token::RegDef(RegDef {
+                    name,
+                    peripheral,
+                    offset: format!("0x{:x}", offset),
+                    kind: RegDefKind::Indexed,
+                })
+            );
+        }
+
+        #[test]
+        fn offset_reg_def_round_trips(name in NAME, offset in 0u32..0x1000) {
+            let line = format!("#define {}_ADDRESS            0x{:02x}", name, offset);
+
+            prop_assert_eq!(
+                Lexer::new().token(&line),
+                Token::RegDef(RegDef {
+                    peripheral: name.split('_').next().unwrap().to_string(),
+                    name,
+                    offset: format!("0x{:02x}", offset),
+                    kind: RegDefKind::Offset,
+                })
+            );
+        }
+
+        #[test]
+        fn mask_round_trips(name in NAME, mask in any::<u32>(), access in prop::sample::select(vec!["RO", "R/W", "WO"])) {
+            let line = format!("#define {}          0x{:08X} // {}", name, mask, access);
+
+            prop_assert_eq!(
+                Lexer::new().token(&line),
+                Token::Mask(Mask {
+                    name,
+                    value: format!("0x{:08X}", mask),
+                    kind: MaskKind::Field,
+                    single_bit: false,
+                    access: Type::from_str(access).ok(),
+                })
+            );
+        }
+
+        #[test]
+        fn single_bit_round_trips(name in NAME, bit in 0u8..32) {
+            let line = format!("#define {}          (BIT({}))", name, bit);
+
+            prop_assert_eq!(
+                Lexer::new().token(&line),
+                Token::Mask(Mask {
+                    name,
+                    value: format!("(BIT({}))", bit),
+                    kind: MaskKind::Field,
+                    single_bit: true,
+                    access: None,
+                })
+            );
+        }
+
+        #[test]
+        fn companions_round_trip(name in NAME, value in 0u32..32) {
+            let lexer = Lexer::new();
+
+            prop_assert_eq!(
+                lexer.token(&format!("#define {}_S        {}", name, value)),
+                Token::Shift(Shift {
+                    name: name.clone(),
+                    value: value.to_string(),
+                    access: None,
+                })
+            );
+            for (suffix, kind) in [("M", MaskKind::Shifted), ("V", MaskKind::Value)].iter() {
+                prop_assert_eq!(
+                    lexer.token(&format!("#define {}_{}        0x{:X}", name, suffix, value)),
+                    Token::Mask(Mask {
+                        name: name.clone(),
+                        value: format!("0x{:X}", value),
+                        kind: *kind,
+                        single_bit: false,
+                        access: None,
+                    })
+                );
+            }
+        }
+
+        #[test]
+        fn base_addr_round_trips(peripheral in "[A-Z][A-Z0-9]{0,8}", address in any::<u32>(), indexed in any::<bool>()) {
+            let line = if indexed {
+                format!("#define REG_{}_BASE(i)    (0x{:08x} + (i)*0xf00)", peripheral, address)
+            } else {
+                format!("#define PERIPHS_{}_BASEADDR    0x{:08x}", peripheral, address)
+            };
+            let define = if indexed {
+                format!("REG_{}_BASE", peripheral)
+            } else {
+                format!("PERIPHS_{}_BASEADDR", peripheral)
+            };
+
+            prop_assert_eq!(
+                Lexer::new().base_addr(&line),
+                Some(BaseAddr { define, peripheral, indexed })
+            );
+        }
+
+        #[test]
+        fn lexing_never_panics(line in "\\PC*") {
+            let lexer = Lexer::new();
+            lexer.token(&line);
+            lexer.base_addr(&line);
+        }
+    }
+}
@@ -1,20 +1,29 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufWriter;
+use std::collections::BTreeMap;
+use std::path::Path;
 
+use log::{debug, info, warn};
 use regex::Regex;
-use svd_parser::encode::Encode;
+use serde::{Deserialize, Serialize};
 
 use crate::common::{
-    build_svd, file_to_string, BitField, Bits, ChipType, Interrupt, Peripheral, Register, Type,
+    add_write_constraints, define_comment, file_to_string, irq_sources, parse_memory_regions,
+    read_dir_sorted, BitField, Bits, Cache, ChipType, Device, Interrupt, Line, Mask, MaskKind,
+    Peripheral, Preprocessor, RegDefKind, Register, Shift, Source, Token, Type,
 };
 
 mod doc_input;
+mod doc_manifest;
 mod doc_parse;
+mod lexer;
 
-pub use doc_parse::parse_doc;
+use lexer::Lexer;
+
+pub use doc_manifest::load_manifest;
+pub use doc_parse::{load_overrides, parse_doc, Overrides};
 
 pub const SOC_BASE_PATH: &'static str = "ESP8266_RTOS_SDK/components/esp8266/include/esp8266/";
+pub const DOC_MANIFEST: &'static str = "doc_tables.json";
+pub const DOC_OVERRIDES: &'static str = "doc_overrides.json";
 
 // make the header a bit more easy to handle
 const REPLACEMENTS: &'static [(&'static str, &'static str)] = &[
@@ -22,393 +31,639 @@ const REPLACEMENTS: &'static [(&'static str, &'static str)] = &[
     ("RTC_STORE0", "RTC_STORE0_REG"),
     ("RTC_STATE1", "RTC_STATE1_REG"),
     ("RTC_STATE2", "RTC_STATE2_REG"),
-    ("(0x60000000 + (i)*0xf00)", "0x60000000"), // uart base address
 ];
 const REPLACEMENTS_REGEX: &'static [(&'static str, &'static str)] = &[
     (r"(I2S[^\s]+)[\s]+(\(REG_I2S_BASE \+ )", "${1}_REG $2"),
     (r"(SLC_[^\s]+)[\s]+(\(REG_SLC_BASE \+ )", "${1}_REG $2"),
 ];
 
-// Regexes to find all the peripheral addresses
-pub const REG_BASE: &'static str =
-    r"\#define[\s*]+(?:DR_REG|REG|PERIPHS)_(.*)_BASE(?:_?A?DDR)?[\s*]+\(?0x([0-9a-fA-F]+)\)?";
-pub const REG_DEF: &'static str = r"\#define[\s*]+(?:PERIPHS_)?([^\s*]+)_(?:REG|ADDRESS|U|ADDR)[\s*]+\((?:DR_REG|REG|PERIPHS)_(.*)_BASE(?:_?A?DDR)? \+ (.*)\)";
-pub const REG_DEF_OFFSET: &'static str =
-    r"\#define[\s*]+(?:PERIPHS_)?([^\s*]+)_(?:ADDRESS|U|ADDR)[\s*]+(?:0x)?([0-9a-fA-F]+)";
-pub const REG_DEF_INDEX: &'static str = r"\#define[\s*]+(?:PERIPHS_)?([^\s*]+)_(?:REG|ADDRESS|U|ADDR)\(i\)[\s*]+\((?:DR_REG|REG|PERIPHS)_([0-9A-Za-z_]+)_BASE(?:_?A?DDR)?[\s*]*\(i\) \+ (.*?)\)";
-pub const REG_DEFINE_MASK: &'static str = r"\#define[\s*]+(?:PERIPHS_)?([^\s*]+)[\s*]+\(?(0x[0-9a-fA-F]+|[0-9]+|\(?BIT\(?[0-9]+\)?)\)?\)?";
-pub const REG_DEFINE_SHIFT: &'static str =
-    r"\#define[\s*]+(?:PERIPHS_)?([^\s*]+)_(?:S|s)[\s*]+\(?(0x[0-9a-fA-F]+|[0-9]+)\)?";
-pub const REG_DEFINE_SKIP: &'static str =
-    r"\#define[\s*]+(?:PERIPHS_)?([^\s*]+)_(?:M|V)[\s*]+(\(|0x)";
-pub const SINGLE_BIT: &'static str = r"BIT\(?([0-9]+)\)?";
-pub const INTERRUPTS: &'static str =
-    r"\#define[\s]ETS_([0-9A-Za-z_/]+)_SOURCE[\s]+([0-9]+)/\*\*<\s([0-9A-Za-z_/\s,]+)\*/";
-pub const REG_IFDEF: &'static str = r"#ifn?def.*";
-pub const REG_ENDIF: &'static str = r"#endif";
+// Access types implied by the naming conventions of the headers
+const ACCESS_SUFFIXES: &'static [(&'static str, Type)] = &[
+    ("_INT_RAW", Type::ReadOnly),
+    ("_INT_ST", Type::ReadOnly),
+    ("_INT_CLR", Type::WriteOnly),
+    ("_STATUS", Type::ReadOnly),
+    ("_W1TS", Type::WriteOnly),
+    ("_W1TC", Type::WriteOnly),
+];
+
+// Access types of fields, or all fields of a register, the headers get wrong
+const ACCESS_OVERRIDES: &'static [(&'static str, Type)] = &[
+    ("GPIO_IN", Type::ReadOnly),
+    ("UART_RXFIFO_RD_BYTE", Type::ReadOnly),
+];
 
 enum State {
     FindReg,
     FindBitFieldMask(String, Register),
-    FindBitFieldShift(String, Register, u32),
-    FindBitFieldSkipShift(String, Register),
+    FindBitFieldDefines(String, Register, FieldDefines),
     AssumeFullRegister(String, Register),
     CheckEnd(String, Register),
     End(String, Register),
 }
 
-fn add_base_addr(header: &str, peripherals: &mut HashMap<String, Peripheral>) {
-    let re_base = Regex::new(REG_BASE).unwrap();
+/// The defines describing a single field: `FOO` holding the mask, or a single
+/// `BIT(n)`, and its `FOO_S`, `FOO_M` and `FOO_V` companions
+#[derive(Clone, Debug, Default)]
+struct FieldDefines {
+    name: String,
+    /// Line of the first define of the field
+    line: usize,
+    mask: Option<u32>,
+    single_bit: bool,
+    shift: Option<u32>,
+    shifted_mask: Option<u32>,
+    value_mask: Option<u32>,
+    /// Access type given in a comment next to one of the defines
+    access: Option<Type>,
+}
 
-    // Peripheral base addresses
-    for captures in re_base.captures_iter(header) {
-        let peripheral = &captures[1];
-        let address = &captures[2];
+impl FieldDefines {
+    fn new(name: &str, line: usize) -> Self {
+        FieldDefines {
+            name: name.to_string(),
+            line,
+            ..Default::default()
+        }
+    }
 
-        let mut p = Peripheral::default();
-        p.address = u32::from_str_radix(address, 16).unwrap();
-        p.description = peripheral.to_string();
+    fn add_mask(&mut self, mask: &Mask, value: Option<i64>) {
+        self.access = self.access.or(mask.access);
 
-        if !peripherals.contains_key(peripheral) {
-            peripherals.insert(peripheral.to_string(), p);
+        let value = value.map(|v| v as u32);
+        match mask.kind {
+            MaskKind::Field => {
+                self.mask = value;
+                self.single_bit = mask.single_bit;
+            }
+            MaskKind::Shifted => self.shifted_mask = value,
+            MaskKind::Value => self.value_mask = value,
         }
     }
-}
 
-fn parse_sdk() -> HashMap<String, Peripheral> {
-    let mut peripherals = HashMap::new();
-    let mut invalid_peripherals = vec![];
-    let mut invalid_files = vec![];
-    let mut invalid_registers = vec![];
-    // let mut invalid_bit_fields = vec![];
+    fn add_shift(&mut self, shift: &Shift, value: Option<i64>) {
+        self.access = self.access.or(shift.access);
+        self.shift = value.map(|v| v as u32);
+    }
 
-    let mut interrupts = vec![];
+    /// Checks the defines agree with each other and builds the field
+    fn to_bit_field(&self, reg_name: &str) -> Result<BitField, String> {
+        let mut bitfield = self.validate()?;
+        bitfield.type_ = access_type(reg_name, &self.name, self.access);
 
-    let filename = SOC_BASE_PATH.to_owned() + "eagle_soc.h";
-    let re_reg = Regex::new(REG_DEF).unwrap();
-    let re_reg_index = Regex::new(REG_DEF_INDEX).unwrap();
-    let re_reg_offset = Regex::new(REG_DEF_OFFSET).unwrap();
-    let re_reg_define = Regex::new(REG_DEFINE_MASK).unwrap();
-    let re_reg_define_shift = Regex::new(REG_DEFINE_SHIFT).unwrap();
-    let re_interrupts = Regex::new(INTERRUPTS).unwrap();
-    let re_single_bit = Regex::new(SINGLE_BIT).unwrap();
-    let re_reg_skip = Regex::new(REG_DEFINE_SKIP).unwrap();
-    let re_ifdef = Regex::new(REG_IFDEF).unwrap();
-    let re_endif = Regex::new(REG_ENDIF).unwrap();
+        Ok(bitfield)
+    }
 
-    let soc_h = file_to_string(&filename);
+    fn validate(&self) -> Result<BitField, String> {
+        let name = &self.name;
 
-    for captures in re_interrupts.captures_iter(soc_h.as_str()) {
-        let name = &captures[1];
-        let index = &captures[2];
-        let desc = &captures[3];
-        let intr = Interrupt {
-            name: name.to_string(),
-            description: Some(desc.to_string()),
-            value: index.parse().unwrap(),
+        if self.single_bit {
+            let mask = self.mask.unwrap_or_default();
+            if mask.count_ones() != 1 {
+                return Err(format!("{} is not a single bit", name));
+            }
+
+            let bit = mask.trailing_zeros();
+            return match self.shift {
+                Some(shift) if shift != bit => Err(format!(
+                    "{} is BIT({}) but {}_S is {}",
+                    name, bit, name, shift
+                )),
+                _ => Ok(BitField {
+                    name: name.to_string(),
+                    bits: Bits::Single(bit as u8),
+                    ..Default::default()
+                }),
+            };
+        }
+
+        let mask = self
+            .mask
+            .or(self.value_mask)
+            .or(self.shifted_mask)
+            .ok_or_else(|| format!("no mask for {}", name))?;
+        if mask == 0 {
+            return Err(format!("{} has an empty mask", name));
+        }
+
+        // the mask may be given either in place or shifted down to bit 0
+        let offset = mask.trailing_zeros();
+        let value = mask >> offset;
+        if value & value.wrapping_add(1) != 0 {
+            return Err(format!("{} has a non-contiguous mask {:#x}", name, mask));
+        }
+
+        let shift = match self.shift {
+            Some(shift) if offset != 0 && offset != shift => {
+                return Err(format!(
+                    "{} mask {:#x} does not match {}_S {}",
+                    name, mask, name, shift
+                ))
+            }
+            Some(shift) => shift,
+            None if offset != 0 => offset,
+            None => return Err(format!("no shift for {}", name)),
         };
-        interrupts.push(intr);
-        // println!("{:#?}", intr);
+
+        let width = value.count_ones();
+        if shift + width > 32 {
+            return Err(format!("{} exceeds the register width", name));
+        }
+
+        let expected = (((1u64 << width) - 1) << shift) as u32;
+        if let Some(shifted_mask) = self.shifted_mask {
+            if shifted_mask != expected {
+                return Err(format!(
+                    "{}_M is {:#x}, expected {:#x}",
+                    name, shifted_mask, expected
+                ));
+            }
+        }
+        if let Some(value_mask) = self.value_mask {
+            if value_mask != value {
+                return Err(format!(
+                    "{}_V is {:#x}, expected {:#x}",
+                    name, value_mask, value
+                ));
+            }
+        }
+
+        Ok(BitField {
+            name: name.to_string(),
+            bits: match width {
+                1 => Bits::Single(shift as u8),
+                _ => Bits::Range(shift as u8..=(shift + width - 1) as u8),
+            },
+            ..Default::default()
+        })
     }
+}
 
-    /*
-       Theses are indexed, we seed these as they cannot be derived from the docs
-       These blocks are identical, so we need to do some post processing to properly index
-       and offset these
-    */
-    // peripherals.insert("I2C".to_string(), Peripheral::default());
-    // peripherals.insert("SPI".to_string(), Peripheral::default());
-    // peripherals.insert("TIMG".to_string(), Peripheral::default());
-    // peripherals.insert("MCPWM".to_string(), Peripheral::default());
-    // peripherals.insert("UHCI".to_string(), Peripheral::default());
+/// Access type of a field, from the overrides, its comment or the naming
+/// conventions of the field and its register, in that order
+/// A field covering the whole register, for registers without fields
+fn full_register_field(reg: &Register) -> BitField {
+    BitField {
+        name: "Register".to_string(),
+        bits: Bits::Range(0..=31),
+        type_: access_type(&reg.name, "Register", None),
+        source: reg.source.clone(),
+        ..Default::default()
+    }
+}
 
-    add_base_addr(&soc_h, &mut peripherals);
+/// Adds the field described by the defines found in `file` to the register
+fn add_field(reg: &mut Register, defines: &FieldDefines, file: &str) -> Result<(), String> {
+    let mut bitfield = defines
+        .to_bit_field(&reg.name)
+        .map_err(|e| format!("{}:{} {}", file, defines.line, e))?;
+    bitfield.source = Some(Source::Header {
+        file: file.to_string(),
+        line: defines.line,
+    });
+    reg.bit_fields.push(bitfield);
+    Ok(())
+}
+
+fn access_type(reg_name: &str, field_name: &str, comment: Option<Type>) -> Type {
+    let overridden = ACCESS_OVERRIDES
+        .iter()
+        .find(|(name, _)| *name == field_name)
+        .or_else(|| ACCESS_OVERRIDES.iter().find(|(name, _)| *name == reg_name));
+    if let Some((_, ty)) = overridden {
+        return *ty;
+    }
 
-    std::fs::read_dir(SOC_BASE_PATH)
-        .unwrap()
-        .filter_map(Result::ok)
-        .filter(|f| {
-            f.path().to_str().unwrap().ends_with("_register.h")
-                || f.file_name().to_str().unwrap() == "eagle_soc.h"
+    if let Some(ty) = comment {
+        return ty;
+    }
+
+    ACCESS_SUFFIXES
+        .iter()
+        .find(|(suffix, _)| field_name.ends_with(suffix))
+        .or_else(|| {
+            ACCESS_SUFFIXES
+                .iter()
+                .find(|(suffix, _)| reg_name.ends_with(suffix))
         })
-        .for_each(|f| {
-            let name = f.path();
-            let name = name.to_str().unwrap();
-            // let mut buffer = vec![];
-            let mut file_data = file_to_string(name);
-            for (search, replace) in REPLACEMENTS {
-                file_data = file_data.replace(search, replace);
-            }
+        .map(|(_, ty)| *ty)
+        .unwrap_or_default()
+}
+
+fn add_base_addr(
+    lexer: &Lexer,
+    lines: &[Line],
+    pp: &Preprocessor,
+    peripherals: &mut BTreeMap<String, Peripheral>,
+) {
+    // Peripheral base addresses
+    for (i, base) in lines
+        .iter()
+        .enumerate()
+        .filter_map(|(i, line)| Some((i, lexer.base_addr(&line.text)?)))
+    {
+        // indexed peripherals are placed at the address of the first instance
+        let define = if base.indexed {
+            format!("{}(0)", base.define)
+        } else {
+            base.define.clone()
+        };
+
+        if let Some(address) = pp.eval(&define) {
+            let mut p = Peripheral::default();
+            p.address = address as u32;
+            p.description = define_comment(lines, i).unwrap_or_else(|| base.peripheral.clone());
 
-            for (search, replace) in REPLACEMENTS_REGEX {
-                let re = Regex::new(search).unwrap();
-                file_data = re.replace_all(&file_data, *replace).to_string();
+            if !peripherals.contains_key(&base.peripheral) {
+                peripherals.insert(base.peripheral, p);
             }
+        }
+    }
+}
 
-            add_base_addr(&file_data, &mut peripherals);
+fn apply_replacements(mut file_data: String) -> String {
+    for (search, replace) in REPLACEMENTS {
+        file_data = file_data.replace(search, replace);
+    }
 
-            // println!("Searching {}", name);
-            let mut something_found = false;
-            let mut state = State::FindReg;
-            for (i, line) in file_data.lines().enumerate() {
-                if re_ifdef.is_match(line) {
-                    continue;
-                } else if re_endif.is_match(line) {
-                    continue;
-                }
+    for (search, replace) in REPLACEMENTS_REGEX {
+        let re = Regex::new(search).unwrap();
+        file_data = re.replace_all(&file_data, *replace).to_string();
+    }
 
-                loop {
-                    match state {
-                        State::FindReg => {
-                            /* Normal register definitions */
-                            if let Some(m) = re_reg.captures(line) {
-                                let reg_name = &m[1];
-                                let pname = &m[2];
-                                let offset = &m[3].trim_start_matches("0x");
-                                if reg_name.ends_with("(i)") {
-                                    invalid_registers.push(reg_name.to_string());
-                                    // some indexed still get through, ignore them
-                                    break;
-                                }
-                                if let Ok(addr) = u32::from_str_radix(offset, 16) {
-                                    let mut r = Register::default();
-                                    r.description = reg_name.to_string();
-                                    r.name = reg_name.to_string();
-                                    r.address = addr;
-                                    state = State::FindBitFieldMask(pname.to_string(), r);
-                                } else {
-                                    invalid_registers.push(reg_name.to_string());
-                                }
-                            } else if let Some(m) = re_reg_index.captures(line) {
-                                let reg_name = &m[1];
-                                let pname = &m[2];
-                                let offset = &m[3].trim_start_matches("0x");
-
-                                if let Ok(addr) = u32::from_str_radix(offset, 16) {
-                                    let mut r = Register::default();
-                                    r.name = reg_name.to_string();
-                                    r.description = reg_name.to_string();
-                                    r.address = addr;
-                                    state = State::FindBitFieldMask(pname.to_string(), r);
-                                } else {
-                                    invalid_registers.push(reg_name.to_string());
-                                }
-                            } else if let Some(m) = re_reg_offset.captures(line) {
-                                let reg_name = &m[1];
-                                let offset = &m[2];
-                                let pname = reg_name.split('_').next().unwrap();
-
-                                if let Ok(addr) = u32::from_str_radix(offset, 16) {
-                                    let mut r = Register::default();
-                                    r.name = reg_name.to_string();
-                                    r.description = reg_name.to_string();
-                                    r.address = addr;
-                                    state = State::FindBitFieldMask(pname.to_string(), r);
-                                } else {
-                                    invalid_registers.push(reg_name.to_string());
-                                }
-                            }
-                            break; // next line
-                        }
-                        State::AssumeFullRegister(ref mut pname, ref mut reg) => {
-                            something_found = true;
-                            // assume full 32bit wide field
-                            let bitfield = BitField {
-                                name: "Register".to_string(),
-                                bits: Bits::Range(0..=31),
-                                ..Default::default()
-                            };
-                            reg.bit_fields.push(bitfield);
+    file_data
+}
 
-                            if let Some(p) = peripherals.get_mut(&pname.to_string()) {
-                                p.registers.push(reg.clone());
-                            } else {
-                                invalid_peripherals.push(pname.to_string());
-                            }
-                            state = State::FindReg;
+/// What was found in a register header, kept in the cache
+#[derive(Default, Serialize, Deserialize)]
+struct Header {
+    /// Peripherals with a base address defined in the header
+    peripherals: BTreeMap<String, Peripheral>,
+    /// Registers along with the name of their peripheral
+    registers: Vec<(String, Register)>,
+    something_found: bool,
+    invalid_registers: Vec<String>,
+    invalid_bit_fields: Vec<String>,
+    /// Registers which exist only in some configurations
+    conditional_registers: Vec<String>,
+    /// Conditionals which could not be evaluated
+    invalid_conditionals: Vec<String>,
+    /// Details of the problems to log, kept so they are logged for cached
+    /// headers too
+    messages: Vec<String>,
+}
+
+fn parse_header(name: &str, file_data: &str, mut pp: Preprocessor, lexer: &Lexer) -> Header {
+    let mut header = Header::default();
+    let lines = pp.process(file_data);
+
+    add_base_addr(lexer, &lines, &pp, &mut header.peripherals);
+
+    // println!("Searching {}", name);
+    let mut state = State::FindReg;
+    for line in &lines {
+        let (i, conditions) = (line.number, &line.conditions);
+        let token = lexer.token(&line.text);
+
+        loop {
+            match state {
+                State::FindReg => {
+                    if let Token::RegDef(def) = &token {
+                        if def.name.ends_with("(i)") {
+                            // some indexed still get through, ignore them
+                            header.invalid_registers.push(def.name.clone());
+                            break;
                         }
-                        State::FindBitFieldMask(ref mut pname, ref mut reg) => {
-                            if re_reg_skip.is_match(line) {
-                                break;
+                        if let Some(addr) = pp.eval(&def.offset) {
+                            let mut r = Register::default();
+                            r.name = def.name.clone();
+                            r.description = def.name.clone();
+                            r.address = addr as u32;
+                            r.source = Some(Source::Header {
+                                file: name.to_string(),
+                                line: i,
+                            });
+                            if !conditions.is_empty() {
+                                header.conditional_registers.push(format!(
+                                    "{} ({})",
+                                    r.name,
+                                    conditions.join(" && ")
+                                ));
                             }
+                            state = State::FindBitFieldMask(def.peripheral.clone(), r);
+                        } else {
+                            header.invalid_registers.push(def.name.clone());
+                        }
+                    }
+                    break; // next line
+                }
+                State::AssumeFullRegister(ref mut pname, ref mut reg) => {
+                    header.something_found = true;
+                    reg.bit_fields.push(full_register_field(reg));
 
-                            if re_reg_offset.is_match(line) {
-                                state = State::AssumeFullRegister(pname.clone(), reg.clone());
-                                continue;
-                            }
-                            if let Some(m) = re_reg_define.captures(line) {
-                                something_found = true;
-                                let define_name = &m[1];
-                                let value = &m[2].trim_start_matches("0x");
-
-                                if let Some(m) = re_single_bit.captures(value) {
-                                    if let Ok(mask_bit) = u8::from_str_radix(&m[1], 10) {
-                                        let bitfield = BitField {
-                                            name: define_name.to_string(),
-                                            bits: Bits::Single(mask_bit),
-                                            ..Default::default()
-                                        };
-                                        reg.bit_fields.push(bitfield);
-                                        state = State::FindBitFieldSkipShift(
-                                            pname.clone(),
-                                            reg.clone(),
-                                        );
-                                        break;
-                                    } else {
-                                        println!(
-                                            "Failed to single bit match reg mask at {}:{}",
-                                            name, i
-                                        );
-                                        state = State::FindReg;
-                                    }
-                                } else if let Ok(mask) = u32::from_str_radix(value, 16) {
-                                    state =
-                                        State::FindBitFieldShift(pname.clone(), reg.clone(), mask);
-                                }
+                    header.registers.push((pname.clone(), reg.clone()));
+                    state = State::FindReg;
+                }
+                State::FindBitFieldMask(ref mut pname, ref mut reg) => {
+                    match &token {
+                        Token::RegDef(def) if def.kind == RegDefKind::Offset => {
+                            // the next register follows without a blank line
+                            state = if reg.bit_fields.is_empty() {
+                                State::AssumeFullRegister(pname.clone(), reg.clone())
                             } else {
-                                if reg.bit_fields.is_empty() {
-                                    state = State::AssumeFullRegister(pname.clone(), reg.clone());
-                                    continue;
-                                } else {
-                                    println!("Failed to match reg mask at {}:{}", name, i);
-                                    state = State::End(pname.clone(), reg.clone());
-                                }
-                            }
-                            break; // next line
+                                State::End(pname.clone(), reg.clone())
+                            };
+                            continue;
                         }
-                        State::FindBitFieldShift(ref mut pname, ref mut reg, ref mut mask) => {
-                            if re_reg_skip.is_match(line) {
-                                break;
-                            }
-                            if let Some(m) = re_reg_define_shift.captures(line) {
-                                let define_name = &m[1];
-                                let value = &m[2];
-
-                                if let Ok(shift) = u8::from_str_radix(value, 10) {
-                                    let bitfield = BitField {
-                                        name: define_name.to_string(),
-                                        bits: match mask.count_ones() {
-                                            1 => Bits::Single(shift),
-                                            bits => Bits::Range(shift..=shift + (bits - 1) as u8),
-                                        },
-                                        ..Default::default()
-                                    };
-                                    reg.bit_fields.push(bitfield);
-                                    state = State::CheckEnd(pname.clone(), reg.clone())
-                                }
+                        Token::Mask(mask) if mask.kind == MaskKind::Field => {
+                            header.something_found = true;
+                            if let Some(value) = pp.eval(&mask.value) {
+                                let mut defines = FieldDefines::new(&mask.name, i);
+                                defines.add_mask(mask, Some(value));
+                                state =
+                                    State::FindBitFieldDefines(pname.clone(), reg.clone(), defines);
                             } else {
-                                if reg.bit_fields.is_empty() {
-                                    state = State::AssumeFullRegister(pname.clone(), reg.clone());
-                                    continue;
-                                } else {
-                                    println!(
-                                        "Failed to match reg shift at {}:{} ('{}')",
-                                        name, i, line
-                                    );
-                                    state = State::End(pname.clone(), reg.clone());
-                                }
+                                header
+                                    .invalid_bit_fields
+                                    .push(format!("{}:{} invalid mask {}", name, i, mask.name));
                             }
-                            break; // next line
                         }
-                        State::FindBitFieldSkipShift(ref mut pname, ref mut reg) => {
-                            state = State::CheckEnd(pname.clone(), reg.clone());
-                            if re_reg_define_shift.is_match(line) {
-                                break;
-                            }
+                        Token::Mask(mask) => {
+                            // `FOO_M` or `FOO_V` listed before `FOO`
+                            header.something_found = true;
+                            let mut defines = FieldDefines::new(&mask.name, i);
+                            defines.add_mask(mask, pp.eval(&mask.value));
+                            state = State::FindBitFieldDefines(pname.clone(), reg.clone(), defines);
                         }
-                        State::CheckEnd(ref mut pname, ref mut reg) => {
-                            if line.is_empty() {
-                                state = State::End(pname.clone(), reg.clone());
-                                break;
-                            } else if re_reg_define.is_match(line) {
-                                // we've found the next bit field in the reg
-                                state = State::FindBitFieldMask(pname.clone(), reg.clone());
-                            } else {
-                                break; // next line
-                            }
+                        Token::Shift(shift) => {
+                            // `FOO_S` listed before `FOO`
+                            header.something_found = true;
+                            let mut defines = FieldDefines::new(&shift.name, i);
+                            defines.add_shift(shift, pp.eval(&shift.value));
+                            state = State::FindBitFieldDefines(pname.clone(), reg.clone(), defines);
                         }
-                        State::End(ref mut pname, ref mut reg) => {
-                            if let Some(p) = peripherals.get_mut(&pname.to_string()) {
-                                p.registers.push(reg.clone());
-                            } else {
-                                // TODO indexed peripherals wont come up here
-                                // println!("No periphal called {}", pname.to_string());
-                                invalid_peripherals.push(pname.to_string());
-                            }
-                            state = State::FindReg;
+                        _ if reg.bit_fields.is_empty() => {
+                            state = State::AssumeFullRegister(pname.clone(), reg.clone());
+                            continue;
+                        }
+                        _ => {
+                            header
+                                .messages
+                                .push(format!("Failed to match reg mask at {}:{}", name, i));
+                            state = State::End(pname.clone(), reg.clone());
+                        }
+                    }
+                    break; // next line
+                }
+                State::FindBitFieldDefines(ref mut pname, ref mut reg, ref mut defines) => {
+                    match &token {
+                        Token::Shift(shift) if shift.name == defines.name => {
+                            defines.add_shift(shift, pp.eval(&shift.value));
+                            break; // next line
+                        }
+                        // the companions, or `FOO` following them
+                        Token::Mask(mask)
+                            if mask.name == defines.name
+                                && (mask.kind != MaskKind::Field || defines.mask.is_none()) =>
+                        {
+                            defines.add_mask(mask, pp.eval(&mask.value));
+                            break; // next line
                         }
+                        _ => {}
+                    }
+
+                    // all defines of the field have been found
+                    if let Err(e) = add_field(reg, defines, name) {
+                        header.invalid_bit_fields.push(e);
                     }
+                    state = State::CheckEnd(pname.clone(), reg.clone());
+                }
+                State::CheckEnd(ref mut pname, ref mut reg) => match token {
+                    Token::Blank => {
+                        state = State::End(pname.clone(), reg.clone());
+                        break;
+                    }
+                    Token::Mask(_) | Token::Shift(_) => {
+                        // we've found the next bit field in the reg
+                        state = State::FindBitFieldMask(pname.clone(), reg.clone());
+                    }
+                    Token::RegDef(_) => {
+                        // the next register follows without a blank line
+                        state = State::End(pname.clone(), reg.clone());
+                    }
+                    _ => break, // next line
+                },
+                State::End(ref mut pname, ref mut reg) => {
+                    header.registers.push((pname.clone(), reg.clone()));
+                    state = State::FindReg;
                 }
             }
+        }
+    }
 
-            // log if nothing was parsed in this file
-            if !something_found {
-                invalid_files.push(String::from(name))
+    // the directives closing the file are not part of the lines,
+    // finish the register still pending at the end
+    let pending = match state {
+        State::FindReg => None,
+        State::FindBitFieldMask(pname, mut reg) | State::AssumeFullRegister(pname, mut reg) => {
+            if reg.bit_fields.is_empty() {
+                header.something_found = true;
+                reg.bit_fields.push(full_register_field(&reg));
             }
+            Some((pname, reg))
+        }
+        State::FindBitFieldDefines(pname, mut reg, defines) => {
+            if let Err(e) = add_field(&mut reg, &defines, name) {
+                header.invalid_bit_fields.push(e);
+            }
+            Some((pname, reg))
+        }
+        State::CheckEnd(pname, reg) | State::End(pname, reg) => Some((pname, reg)),
+    };
+    header.registers.extend(pending);
+
+    // registers which exist in another configuration
+    for line in pp.excluded.drain(..) {
+        if let Some(def) = lexer.reg_def(&line.text) {
+            header.conditional_registers.push(format!(
+                "{} (excluded, {})",
+                def.name,
+                line.conditions.join(" && ")
+            ));
+        }
+    }
+
+    header.invalid_conditionals = pp
+        .warnings
+        .into_iter()
+        .map(|w| format!("{}:{}", name, w))
+        .collect();
+
+    header
+}
+
+fn parse_sdk(pp: &Preprocessor, cache: &Cache) -> Device {
+    let mut peripherals = BTreeMap::new();
+    let mut invalid_peripherals = vec![];
+    let mut invalid_files = vec![];
+    let mut invalid_registers = vec![];
+    let mut invalid_conditionals = vec![];
+    let mut conditional_registers = vec![];
+    let mut invalid_bit_fields = vec![];
+
+    let mut interrupts = vec![];
+
+    let filename = SOC_BASE_PATH.to_owned() + "eagle_soc.h";
+    let lexer = Lexer::new();
+
+    let soc_h = file_to_string(&filename);
+
+    // macros from the soc header are available to all register headers
+    let mut soc_pp = pp.clone();
+    let soc_lines = soc_pp.process(&apply_replacements(soc_h.clone()));
+    soc_pp.excluded.clear(); // reported when parsing the soc header itself
+    invalid_conditionals.extend(
+        soc_pp
+            .warnings
+            .drain(..)
+            .map(|w| format!("{}:{}", filename, w)),
+    );
+    let memory = parse_memory_regions(&soc_lines, &soc_pp, "sdk");
+
+    let irqs = irq_sources(&soc_lines);
+    irqs.report("sdk");
+    for source in irqs.sources {
+        interrupts.push(Interrupt {
+            name: source.name,
+            description: source.description,
+            value: source.value,
         });
+    }
 
-    println!("Parsed idf for peripherals information.");
+    /*
+       Theses are indexed, we seed these as they cannot be derived from the docs
+       These blocks are identical, so we need to do some post processing to properly index
+       and offset these
+    */
+    // peripherals.insert("I2C".to_string(), Peripheral::default());
+    // peripherals.insert("SPI".to_string(), Peripheral::default());
+    // peripherals.insert("TIMG".to_string(), Peripheral::default());
+    // peripherals.insert("MCPWM".to_string(), Peripheral::default());
+    // peripherals.insert("UHCI".to_string(), Peripheral::default());
+
+    add_base_addr(&lexer, &soc_lines, &soc_pp, &mut peripherals);
+
+    // the headers are merged in the order of the files. A header only has to
+    // be parsed again when it or the macros it's evaluated against changed.
+    let files: Vec<String> = read_dir_sorted(SOC_BASE_PATH)
+        .into_iter()
+        .map(|f| f.path().to_str().unwrap().to_string())
+        .filter(|name| name.ends_with("_register.h") || *name == filename)
+        .collect();
+    for name in &files {
+        // the soc header has been processed already, its include guard
+        // would hide all of it
+        let pp = if *name == filename {
+            pp.clone()
+        } else {
+            soc_pp.clone()
+        };
+        let file_data = apply_replacements(file_to_string(name));
+        let key = Cache::key(&[name, &pp.fingerprint(), &file_data]);
+        let header = cache.get("header", &key).unwrap_or_else(|| {
+            let header = parse_header(name, &file_data, pp, &lexer);
+            cache.put("header", &key, &header);
+            header
+        });
+
+        header
+            .messages
+            .iter()
+            .for_each(|m| debug!(target: "sdk", "{}", m));
+
+        for (pname, p) in header.peripherals {
+            peripherals.entry(pname).or_insert(p);
+        }
+        for (pname, reg) in header.registers {
+            if let Some(p) = peripherals.get_mut(&pname) {
+                p.registers.push(reg);
+            } else {
+                // TODO indexed peripherals wont come up here
+                invalid_peripherals.push(pname);
+            }
+        }
+
+        // log if nothing was parsed in this file
+        if !header.something_found {
+            invalid_files.push(name.clone())
+        }
+
+        invalid_registers.extend(header.invalid_registers);
+        invalid_bit_fields.extend(header.invalid_bit_fields);
+        conditional_registers.extend(header.conditional_registers);
+        invalid_conditionals.extend(header.invalid_conditionals);
+    }
+
+    debug!(target: "sdk", "Parsed the headers for peripherals information.");
 
     if invalid_files.len() > 0 {
-        println!(
+        warn!(
+            target: "sdk",
             "The following files contained no parsable information {:?}",
             invalid_files
         );
     }
 
     if invalid_peripherals.len() > 0 {
-        println!(
+        warn!(
+            target: "sdk",
             "The following peripherals failed to parse {:?}",
             invalid_peripherals
         );
     }
 
     if invalid_registers.len() > 0 {
-        println!(
+        warn!(
+            target: "sdk",
             "The following registers failed to parse {:?}",
             invalid_registers
         );
     }
 
-    // if invalid_bit_fields.len() > 0 {
-    //     println!(
-    //         "The following bit_fields failed to parse {:?}",
-    //         invalid_bit_fields
-    //     );
-    // }
-
-    // println!("Interrupt information: {:#?}", interrupts);
-
-    peripherals
-}
+    if invalid_conditionals.len() > 0 {
+        warn!(
+            target: "sdk",
+            "The following conditionals could not be evaluated {:?}",
+            invalid_conditionals
+        );
+    }
 
-pub fn create_svd() {
-    let mut peripherals = parse_sdk();
+    if conditional_registers.len() > 0 {
+        info!(
+            target: "sdk",
+            "The following registers depend on the configuration {:?}",
+            conditional_registers
+        );
+    }
 
-    // where available, the docs provide more detailed info
-    peripherals
-        .iter_mut()
-        .for_each(|(name, peripheral)| match name.as_str() {
-            "TIMER" => {
-                let doc_peripheral = parse_doc("build/timer.json");
-                peripheral.registers = doc_peripheral.registers;
-            }
-            "GPIO" => {
-                let doc_peripheral = parse_doc("build/gpio.json");
-                peripheral.registers = doc_peripheral.registers;
-            }
-            _ => {}
-        });
+    if invalid_bit_fields.len() > 0 {
+        warn!(
+            target: "sdk",
+            "The following bit_fields failed to parse {:?}",
+            invalid_bit_fields
+        );
+    }
 
-    let mut uart_peripheral_0 = parse_doc("build/uart.json");
-    let mut uart_peripheral_1 = uart_peripheral_0.clone();
-    uart_peripheral_0.address = 0x60000000;
-    uart_peripheral_1.address = 0x60000f00;
-    peripherals.insert("UART0".to_string(), uart_peripheral_0);
-    peripherals.insert("UART1".to_string(), uart_peripheral_1);
+    Device {
+        chip: ChipType::ESP8266,
+        peripherals,
+        interrupts,
+        memory,
+    }
+}
 
-    let mut spi = parse_doc("build/spi.json");
-    spi.address = 0x60000100;
+/// The SPI data buffer is not part of the register table in the docs
+fn add_spi_buffer(spi: &mut Peripheral) {
     for i in 0..16 {
         spi.registers.push(Register {
             name: format!("SPI_W{}", i),
@@ -422,17 +677,81 @@ pub fn create_svd() {
                 type_: Type::ReadWrite,
                 reset_value: 0,
                 description: format!("the data inside the buffer of the SPI module, word {}", i),
+                access: None,
+                source: None,
+                write_constraint: None,
             }],
             detailed_description: None,
+            source: None,
         })
     }
-    peripherals.insert("SPI1".to_string(), spi.clone());
-    spi.address = 0x60000200;
-    peripherals.insert("SPI0".to_string(), spi);
+}
+
+pub fn create_device(pp: &Preprocessor, cache: &Cache) -> Device {
+    let mut device = parse_sdk(pp, cache);
+    let peripherals = &mut device.peripherals;
+
+    // where available, the docs provide more detailed info
+    let overrides = load_overrides(DOC_OVERRIDES).unwrap_or_else(|e| {
+        warn!(target: "doc", "Failed to load the row overrides, {}", e);
+        Overrides::default()
+    });
+    let tables = load_manifest(DOC_MANIFEST).unwrap_or_else(|e| {
+        warn!(target: "doc", "Failed to load the doc tables, {}", e);
+        vec![]
+    });
+    for table in tables {
+        let path = table.path();
+        if !Path::new(&path).exists() {
+            warn!(
+                target: "doc",
+                "Missing {} (pages {}), run `make` to extract it from the technical reference",
+                path, table.pages
+            );
+            continue;
+        }
+
+        let mut doc_peripheral = match parse_doc(&table.table, &path, &overrides) {
+            Ok(peripheral) => peripheral,
+            Err(e) => {
+                warn!(target: "doc", "Failed to parse {}, skipping", e);
+                continue;
+            }
+        };
+        if table.peripheral == "SPI" {
+            add_spi_buffer(&mut doc_peripheral);
+        }
 
-    let mut svd = build_svd(ChipType::ESP8266, peripherals).unwrap();
-    svd.address_unit_bits = Some(8);
+        // the headers name peripherals with multiple instances after their
+        // base, the instances take its place
+        let base = if table.instances > 1 {
+            peripherals.remove(&table.peripheral)
+        } else {
+            None
+        };
+
+        for (name, address) in table.instances() {
+            let mut peripheral = doc_peripheral.clone();
+            peripheral.description = peripherals
+                .get(&name)
+                .or_else(|| base.as_ref())
+                .map(|p| p.description.clone())
+                .filter(|description| *description != table.peripheral)
+                .unwrap_or_else(|| name.clone());
+            peripheral.address = match (address, peripherals.get(&name)) {
+                (Some(address), _) => address,
+                (None, Some(p)) => p.address,
+                (None, None) => {
+                    warn!(target: "doc", "No base address for {}, skipping", name);
+                    continue;
+                }
+            };
+            peripherals.insert(name, peripheral);
+        }
+    }
 
-    let f = BufWriter::new(File::create("esp8266.svd").unwrap());
-    svd.encode().unwrap().write(f).unwrap();
+    device.sort();
+    device.assign_widths();
+    add_write_constraints(&mut device);
+    device
 }
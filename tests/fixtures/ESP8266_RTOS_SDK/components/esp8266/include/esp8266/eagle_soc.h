@@ -0,0 +1,36 @@
+#ifndef _EAGLE_SOC_H_
+#define _EAGLE_SOC_H_
+
+#define BIT31   0x80000000
+#define BIT4    0x00000010
+#define BIT1    0x00000002
+#define BIT0    0x00000001
+
+#define PERIPHS_DPORT_BASEADDR      0x3ff00000
+#define PERIPHS_GPIO_BASEADDR       0x60000300 // general purpose IO
+#define PERIPHS_TIMER_BASEDDR       0x60000600
+#define REG_UART_BASE(i)            (0x60000000 + (i)*0xf00)
+#define REG_SPI_BASE(i)             (0x60000200 - (i)*0x100)
+
+#define SOC_IRAM_LOW    0x40100000
+#define SOC_IRAM_HIGH   (SOC_IRAM_LOW + 0x8000)
+#define SOC_DRAM_LOW    0x3FFE8000
+#define SOC_DRAM_HIGH   0x40000000
+
+#define ETS_SLC_SOURCE          1/**< interrupt of SLC*/
+#define ETS_SPI_SOURCE          2/**< interrupt of SPI*/
+#define ETS_UART_SOURCE         5/**< interrupt of UART*/
+#define ETS_FRC_TIMER1_SOURCE   9/**< interrupt of FRC1*/
+
+#define GPIO_OUT_ADDRESS            0x00
+#define GPIO_BT_SEL                 0x0000ffff
+#define GPIO_BT_SEL_S               16
+#define GPIO_OUT_DATA               0x0000ffff
+#define GPIO_OUT_DATA_S             0
+#define GPIO_OUT_W1TS_ADDRESS       0x04
+#define GPIO_OUT_W1TC_ADDRESS       0x08
+
+#define EDGE_INT_ENABLE_REG     (PERIPHS_DPORT_BASEADDR + 0x04)
+#define TM1_EDGE_INT_ENABLE     BIT1
+
+#endif
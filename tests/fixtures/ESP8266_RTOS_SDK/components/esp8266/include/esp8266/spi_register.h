@@ -0,0 +1,9 @@
+#ifndef _SPI_REGISTER_H_
+#define _SPI_REGISTER_H_
+
+#define SPI_CMD_REG(i)                  (REG_SPI_BASE(i) + 0x0)
+#define SPI_FLASH_READ                  BIT31
+#define SPI_FLASH_READ_S                31
+#define SPI_USR                         (BIT(18))
+
+#endif // _SPI_REGISTER_H_
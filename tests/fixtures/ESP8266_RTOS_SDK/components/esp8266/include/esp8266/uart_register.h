@@ -0,0 +1,36 @@
+#ifndef _UART_REGISTER_H_
+#define _UART_REGISTER_H_
+
+#define UART_FIFO_REG(i)                (REG_UART_BASE(i) + 0x0)
+#define UART_RXFIFO_RD_BYTE             0x000000FF
+#define UART_RXFIFO_RD_BYTE_S           0
+
+#define UART_INT_RAW_REG(i)             (REG_UART_BASE(i) + 0x4)
+#define UART_RXFIFO_TOUT_INT_RAW        (BIT(8))
+#define UART_RXFIFO_FULL_INT_RAW        (BIT(0))
+
+#define UART_CONF0_REG(i)               (REG_UART_BASE(i) + 0x20)
+#define UART_TXFIFO_RST                 (BIT(18))
+#define UART_TXFIFO_RST_S               18
+#define UART_BIT_NUM                    0x00000003 // R/W
+#define UART_BIT_NUM_S                  2
+#define UART_PARITY_EN_S                1
+#define UART_PARITY_EN                  (BIT(1))
+#define UART_BAD_MASK                   0x00000005
+#define UART_BAD_MASK_S                 4
+
+#define UART_CLKDIV_REG(i)              (REG_UART_BASE(i) + 0x14)
+#define UART_CLKDIV_CNT                 0x000FFFFF
+#define UART_CLKDIV_CNT_M               ((UART_CLKDIV_CNT_V)<<(UART_CLKDIV_CNT_S))
+#define UART_CLKDIV_CNT_V               0xFFFFF
+#define UART_CLKDIV_CNT_S               0
+
+#ifdef CONFIG_FIXTURE_UART_DEBUG
+#define UART_DEBUG_REG(i)               (REG_UART_BASE(i) + 0x60)
+#endif
+
+#define UART_DATE_REG(i)                (REG_UART_BASE(i) + 0x78)
+
+#define UART_ID_REG(i)                  (REG_UART_BASE(i) + 0x7C)
+
+#endif // _UART_REGISTER_H_
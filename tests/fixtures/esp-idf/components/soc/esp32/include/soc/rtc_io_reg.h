@@ -0,0 +1,19 @@
+#ifndef _SOC_RTC_IO_REG_H_
+#define _SOC_RTC_IO_REG_H_
+
+#include "soc.h"
+
+#define RTC_GPIO_OUT_REG          (DR_REG_RTCIO_BASE + 0x0)
+/* RTC_GPIO_OUT_DATA : R/W ;bitpos:[31:14] ;default: 0 ; */
+/*description: GPIO0~17 output value*/
+#define RTC_GPIO_OUT_DATA  0x0003FFFF
+#define RTC_GPIO_OUT_DATA_M  ((RTC_GPIO_OUT_DATA_V)<<(RTC_GPIO_OUT_DATA_S))
+#define RTC_GPIO_OUT_DATA_V  0x3FFFF
+#define RTC_GPIO_OUT_DATA_S  14
+
+#define RTC_GPIO_IN_REG          (DR_REG_RTCIO_BASE + 0x24)
+/* RTC_GPIO_IN_NEXT : RO ;bitpos:[31:14] ;default:  ; */
+/*description: the input value of the pads*/
+#define RTC_GPIO_IN_NEXT  0x0003FFFF
+
+#endif /* _SOC_RTC_IO_REG_H_ */
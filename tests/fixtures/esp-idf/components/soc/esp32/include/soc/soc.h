@@ -0,0 +1,29 @@
+#ifndef _ESP32_SOC_H_
+#define _ESP32_SOC_H_
+
+#define DR_REG_DPORT_BASE                       0x3ff00000
+#define DR_REG_UART_BASE                        0x3ff40000
+/* RTC GPIO, the pads which remain
+ * powered in deep sleep */
+#define DR_REG_RTCIO_BASE                       0x3ff48400
+#define DR_REG_UART1_BASE                       0x3ff50000
+#ifdef CONFIG_FIXTURE_UART2
+#define DR_REG_UART2_BASE                       0x3ff6E000
+#endif
+#define REG_UART_BASE( i )  (DR_REG_UART_BASE + (i) * 0x10000)
+
+#define SOC_IROM_LOW    0x400D0000
+#define SOC_IROM_HIGH   0x40400000
+#define SOC_DRAM_LOW    0x3FFAE000
+#define SOC_DRAM_HIGH   0x40000000
+
+#define ETS_UART0_INTR_SOURCE                   34/**< interrupt of UART0, level*/
+#define ETS_UART1_INTR_SOURCE                   35/**< interrupt of UART1, level*/
+#define ETS_RTC_CORE_INTR_SOURCE                46/**< interrupt of rtc core, level, include rtc watchdog*/
+#define ETS_TG0_T0_LEVEL_INTR_SOURCE            14/**< interrupt of TIMER_GROUP0, TIMER0, level (see TRM, ch. 18)*/
+#define ETS_TG0_T0_EDGE_INTR_SOURCE             14/**< duplicates the number of the level interrupt*/
+#define ETS_PWM0_INTR_SOURCE                    39/**< interrupt of PWM0, level, Reserved
+                                                     for the motor control (MCPWM) driver*/
+#define ETS_CACHE_IA_INTR_SOURCE                68
+
+#endif /* _ESP32_SOC_H_ */
@@ -0,0 +1,61 @@
+#ifndef __UART_REG_H__
+#define __UART_REG_H__
+
+#include "soc.h"
+
+#define UART_FIFO_REG(i)          (REG_UART_BASE(i) + 0x0)
+/* UART_RXFIFO_RD_BYTE : RO ;bitpos:[7:0] ;default: 8'b0 ; */
+/*description: This register stores one byte data  read by rx fifo.*/
+#define UART_RXFIFO_RD_BYTE  0x000000FF
+#define UART_RXFIFO_RD_BYTE_M  ((UART_RXFIFO_RD_BYTE_V)<<(UART_RXFIFO_RD_BYTE_S))
+#define UART_RXFIFO_RD_BYTE_V  0xFF
+#define UART_RXFIFO_RD_BYTE_S  0
+
+#define UART_INT_CLR_REG(i)          (REG_UART_BASE(i) + 0x10)
+/* UART_TXFIFO_EMPTY_INT_CLR : WO ;bitpos:[1] ;default: 1'b0 ; */
+/*description: Set this bit to clear txfifo_empty_int_raw interrupt.*/
+#define UART_TXFIFO_EMPTY_INT_CLR  (BIT(1))
+#define UART_TXFIFO_EMPTY_INT_CLR_M  (BIT(1))
+#define UART_TXFIFO_EMPTY_INT_CLR_V  0x1
+#define UART_TXFIFO_EMPTY_INT_CLR_S  1
+/* UART_RXFIFO_FULL_INT_CLR : WO ;bitpos:[0] ;default: 1'b0 ; */
+/*description: Set this bit to clear the rxfifo_full_int_raw interrupt.*/
+#define UART_RXFIFO_FULL_INT_CLR  (BIT(0))
+#define UART_RXFIFO_FULL_INT_CLR_M  (BIT(0))
+#define UART_RXFIFO_FULL_INT_CLR_V  0x1
+#define UART_RXFIFO_FULL_INT_CLR_S  0
+
+#define UART_CONF1_REG(i)          (REG_UART_BASE(i) + 0x24)
+/* UART_RX_TOUT_EN : R/W ;bitpos:[31] ;default: 1'b0 ; */
+/*description: This is the enable bit for uart receiver's timeout function.
+ The timeout is configured in UART_RX_TOUT_THRHD.*/
+#define UART_RX_TOUT_EN  (BIT(31))
+#define UART_RX_TOUT_EN_M  (BIT(31))
+#define UART_RX_TOUT_EN_V  0x1
+#define UART_RX_TOUT_EN_S  31
+/* UART_RXFIFO_FULL_THRHD : R/W ;bitpos:[6:0] ;default: 7'h60 ; */
+/*description: When receiver receives more data than its threshold value
+ receiver will produce rxfifo_full_int_raw interrupt. The valid range is 1~127.*/
+#define UART_RXFIFO_FULL_THRHD  0x0000007F
+#define UART_RXFIFO_FULL_THRHD_M  ((UART_RXFIFO_FULL_THRHD_V)<<(UART_RXFIFO_FULL_THRHD_S))
+#define UART_RXFIFO_FULL_THRHD_V  0x7F
+#define UART_RXFIFO_FULL_THRHD_S  0
+
+#ifdef CONFIG_FIXTURE_UART_DEBUG
+#define UART_DEBUG_REG(i)          (REG_UART_BASE(i) + 0x60)
+/* UART_DEBUG : R/W ;bitpos:[31:0] ;default: 32'h0 ; */
+/*description: Only exists with CONFIG_FIXTURE_UART_DEBUG.*/
+#define UART_DEBUG  0xFFFFFFFF
+#define UART_DEBUG_M  ((UART_DEBUG_V)<<(UART_DEBUG_S))
+#define UART_DEBUG_V  0xFFFFFFFF
+#define UART_DEBUG_S  0
+
+#endif
+#define UART_DATE_REG(i)          (REG_UART_BASE(i) + 0x78)
+/* UART_DATE : R/W ;bitpos:[31:0] ;default: 32'h15122500 ; */
+/*description: */
+#define UART_DATE  0xFFFFFFFF
+#define UART_DATE_M  ((UART_DATE_V)<<(UART_DATE_S))
+#define UART_DATE_V  0xFFFFFFFF
+#define UART_DATE_S  0
+#endif /*__UART_REG_H__ */
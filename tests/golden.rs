@@ -0,0 +1,113 @@
+//! Runs header2svd on the fixture headers and technical reference tables in
+//! `tests/fixtures` and compares the output against the snapshots in
+//! `tests/golden`, so changes to the parsers show up as reviewable diffs.
+//!
+//! After checking the differences are intended, run the tests with
+//! `UPDATE_GOLDEN=1` to replace the snapshots with the current output.
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+use xmltree::Element;
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn golden(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+}
+
+/// Generates `name` for the chip, the paths of the headers are relative to
+/// the fixtures just as they are relative to the repository when run normally
+fn generate(chip: &str, emit: &str, name: &str) -> String {
+    let out = env::temp_dir().join(format!("header2svd-{}-{}", std::process::id(), name));
+    let output = Command::new(env!("CARGO_BIN_EXE_header2svd"))
+        .current_dir(fixtures())
+        .args([chip, "--emit", emit, "-o"])
+        .arg(&out)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "header2svd {} --emit {} failed\n{}{}",
+        chip,
+        emit,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let generated = fs::read_to_string(&out).unwrap();
+    fs::remove_file(&out).unwrap();
+
+    // keep the snapshots stable across releases
+    generated.replace(env!("CARGO_PKG_VERSION"), "VERSION")
+}
+
+/// The attributes of an element are written in no particular order, so SVDs
+/// are compared as parsed elements
+fn same_xml(generated: &str, expected: &str) -> bool {
+    match (
+        Element::parse(generated.as_bytes()),
+        Element::parse(expected.as_bytes()),
+    ) {
+        (Ok(generated), Ok(expected)) => generated == expected,
+        _ => false,
+    }
+}
+
+fn check(chip: &str, emit: &str, name: &str) {
+    let generated = generate(chip, emit, name);
+    let path = golden(name);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, generated).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "{} is missing, run with UPDATE_GOLDEN=1 to create it",
+            path.display()
+        )
+    });
+    if generated == expected || name.ends_with(".svd") && same_xml(&generated, &expected) {
+        return;
+    }
+
+    let line = generated
+        .lines()
+        .zip(expected.lines())
+        .position(|(g, e)| g != e)
+        .unwrap_or_else(|| generated.lines().count().min(expected.lines().count()));
+    panic!(
+        "{} differs from the output at line {}\nexpected: {:?}\ngenerated: {:?}\n\
+         run with UPDATE_GOLDEN=1 to update it if the change is intended",
+        path.display(),
+        line + 1,
+        expected.lines().nth(line).unwrap_or_default(),
+        generated.lines().nth(line).unwrap_or_default()
+    );
+}
+
+#[test]
+fn esp32_svd() {
+    check("esp32", "svd", "esp32.svd");
+}
+
+#[test]
+fn esp32_model() {
+    check("esp32", "model-yaml", "esp32.yaml");
+}
+
+#[test]
+fn esp8266_svd() {
+    check("esp8266", "svd", "esp8266.svd");
+}
+
+#[test]
+fn esp8266_model() {
+    check("esp8266", "model-yaml", "esp8266.yaml");
+}